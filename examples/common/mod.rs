@@ -6,17 +6,23 @@ pub struct Opts {
 	/// Optional theme name (eg. "light"). Valid names are defined by the crate theme list.
 	#[arg(long, value_name = "THEME")]
 	pub theme: Option<String>,
+	/// Use plain `[tag]`/`[file]` markers instead of Nerd Font icons.
+	#[arg(long)]
+	pub no_icons: bool,
 }
 
-/// Apply the chosen theme (if any) to the provided Searcher and return it.
+/// Apply the chosen theme and icon options (if any) to the provided Searcher and return it.
 ///
 /// Theme selection is currently a no-op because the `nucleo-picker` integration
 /// does not expose theme configuration.
-use git_sparta::picker::SearchUi;
+use git_sparta::picker::{IconStyle, SearchUi};
 
 pub fn apply_theme(searcher: SearchUi, opts: &Opts) -> SearchUi {
 	if let Some(_name) = opts.theme.as_deref() {
 		// Theme selection is not currently supported by the nucleo picker integration.
 	}
+	if opts.no_icons {
+		return searcher.with_icon_style(IconStyle::Ascii);
+	}
 	searcher
 }
@@ -0,0 +1,126 @@
+//! Crash-safe file writes.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Write `contents` to `path` by writing a temporary file in the same directory and renaming
+/// it over the target, so readers never observe a partially written file.
+///
+/// An existing file keeps its permissions; a new one gets the usual umask-derived mode rather
+/// than the owner-only mode of a temporary file.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+	let dir = match path.parent() {
+		Some(parent) if !parent.as_os_str().is_empty() => parent,
+		_ => Path::new("."),
+	};
+
+	let mut builder = tempfile::Builder::new();
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		builder.permissions(fs::Permissions::from_mode(0o666));
+	}
+	let mut temp = builder
+		.tempfile_in(dir)
+		.with_context(|| format!("failed to create a temporary file in {}", dir.display()))?;
+	if let Ok(metadata) = fs::metadata(path) {
+		temp.as_file()
+			.set_permissions(metadata.permissions())
+			.with_context(|| format!("failed to copy the permissions of {}", path.display()))?;
+	}
+	temp.write_all(contents.as_ref())?;
+	temp.as_file().sync_all()?;
+	temp.persist(path)
+		.with_context(|| format!("failed to write {}", path.display()))?;
+	Ok(())
+}
+
+/// Like [`write()`], but first copy any existing file to its [`backup_path`].
+pub fn write_with_backup(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+	if path.exists() {
+		let backup = backup_path(path);
+		fs::copy(path, &backup).with_context(|| {
+			format!(
+				"failed to back up {} to {}",
+				path.display(),
+				backup.display()
+			)
+		})?;
+	}
+	write(path, contents)
+}
+
+/// `path` with `.bak` appended to its file name, e.g. `.gitmodules.bak`.
+pub fn backup_path(path: &Path) -> PathBuf {
+	let mut name = path.file_name().unwrap_or_default().to_os_string();
+	name.push(".bak");
+	path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+
+	#[test]
+	fn test_write_replaces_contents_without_leftovers() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("out.json");
+		fs::write(&path, "old").unwrap();
+
+		write(&path, "new").unwrap();
+
+		assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+		assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_write_keeps_existing_permissions() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let dir = tempfile::tempdir().unwrap();
+		let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+		for expected in [0o644, 0o755, 0o600] {
+			let path = dir.path().join(format!("file-{expected:o}"));
+			fs::write(&path, "old").unwrap();
+			fs::set_permissions(&path, fs::Permissions::from_mode(expected)).unwrap();
+
+			write(&path, "new").unwrap();
+			assert_eq!(mode(&path), expected);
+		}
+
+		// A new file is not restricted to its owner like a bare temporary file.
+		let path = dir.path().join("new");
+		write(&path, "new").unwrap();
+		let created = fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(dir.path().join("reference"))
+			.unwrap();
+		assert_eq!(
+			mode(&path),
+			created.metadata().unwrap().permissions().mode() & 0o777
+		);
+	}
+
+	#[test]
+	fn test_write_with_backup_keeps_previous_contents() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join(".gitmodules");
+
+		write_with_backup(&path, "first").unwrap();
+		assert!(!backup_path(&path).exists());
+
+		write_with_backup(&path, "second").unwrap();
+		assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+		assert_eq!(
+			fs::read_to_string(dir.path().join(".gitmodules.bak")).unwrap(),
+			"first"
+		);
+	}
+}
@@ -1,42 +1,145 @@
 //! Generate sparse-checkout patterns for a project tag.
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::Serialize;
 
+use crate::config::Config;
 use crate::git::{self, attributes};
-use crate::picker;
+use crate::{atomic, output, picker};
+
+/// Options for [`run`], one per `generate-sparse-list` flag.
+#[derive(Debug, Default)]
+pub struct Options<'a> {
+	/// Tag to match; `None` shows the tag picker.
+	pub tag: Option<&'a str>,
+	pub auto_yes: bool,
+	pub repo_dir: Option<&'a Path>,
+	pub attribute: &'a str,
+	/// Setup configuration supplying the tag and repository when they aren't given.
+	pub config: Option<&'a Config>,
+	/// Read tags from stdin instead of `tag` or the picker.
+	pub stdin: bool,
+	/// Existing sparse checkout (worktree or git dir) whose tags are pre-selected.
+	pub from_sparse: Option<&'a Path>,
+	/// Report the estimated size of the selection.
+	pub size: bool,
+	/// Terminate each printed pattern with NUL instead of a newline.
+	pub print0: bool,
+	/// Print the selected tag before the patterns; several tags are joined with commas.
+	pub print_tag: bool,
+	/// Also write a JSON manifest of the selection to this path.
+	pub manifest: Option<&'a Path>,
+	/// Collapse fully selected directories into `/dir/` patterns.
+	pub compact: bool,
+	/// Prefix each printed pattern with the number of matched files it covers.
+	pub counts: bool,
+	pub pattern_options: attributes::PatternOptions,
+}
+
+pub fn run(options: Options<'_>) -> Result<()> {
+	let Options {
+		tag,
+		auto_yes,
+		repo_dir,
+		attribute,
+		config,
+		stdin,
+		from_sparse,
+		size,
+		print0,
+		print_tag,
+		manifest,
+		compact,
+		counts,
+		pattern_options,
+	} = options;
+
+	// A setup config supplies the tag and repository when they aren't given explicitly.
+	let tag = tag.or(config.map(|config| config.project_tag.as_str()));
+	let repo_dir = repo_dir.or(config.map(Config::attributes_repo_path));
+
+	// Fully headless: only patterns are needed, so take the library path. An empty result
+	// falls through to the full scan below, which explains why nothing matched.
+	let needs_scan = stdin || from_sparse.is_some() || size || manifest.is_some() || compact;
+	if let Some(tag) = tag
+		&& auto_yes
+		&& !needs_scan
+	{
+		let repo_dir = repo_dir.unwrap_or_else(|| Path::new("."));
+		let patterns = attributes::sparse_patterns_for(repo_dir, tag, attribute, &pattern_options)?;
+		if !patterns.is_empty() {
+			let patterns = patterns.into_iter().map(|pattern| (pattern, 1)).collect();
+			return print_patterns(print_tag.then_some(tag), &patterns, print0, counts);
+		}
+	}
 
-pub fn run(
-	tag: Option<&str>,
-	auto_yes: bool,
-	repo_dir: Option<&Path>,
-	attribute: &str,
-) -> Result<()> {
 	let (repo, root) = git::open_repository(repo_dir)?;
-	let worktree = git::require_worktree(&repo)?;
 
-	// If no tag provided and not auto-yes, discover available tags and show picker
+	if tag.is_none() && auto_yes && !stdin && from_sparse.is_none() {
+		anyhow::bail!(
+			"tag argument is required when using --yes; run without --yes to select interactively"
+		);
+	}
+
+	// A single scan serves both the tag picker and the pattern collection below. Bare
+	// repositories are scanned from their HEAD tree.
+	let scan = attributes::scan_repository_with(&repo, attribute, &pattern_options)?;
+
+	let tags = if stdin {
+		Some(read_stdin_tags()?)
+	} else if let Some(source) = from_sparse {
+		Some(select_tags_from_sparse(
+			&scan, &root, attribute, source, auto_yes,
+		)?)
+	} else {
+		None
+	};
+
+	if let Some(tags) = tags {
+		let state = scan.collect_matching_any(&tags);
+		if state.matches.is_empty() {
+			if let Some(message) = empty_scan_message(&scan, &root, attribute) {
+				anyhow::bail!(message);
+			}
+			anyhow::bail!(
+				"no matching attribute entries found for tags {} in {}",
+				tags.join(", "),
+				root.display()
+			);
+		}
+		if size {
+			report_size(&repo, &state.patterns)?;
+		}
+		if let Some(path) = manifest {
+			write_manifest(path, &root, attribute, &tags, &state)?;
+		}
+		let tag_record = tags.join(",");
+		return print_patterns(
+			print_tag.then_some(tag_record.as_str()),
+			&output_patterns(&scan, state.patterns, compact),
+			print0,
+			counts,
+		);
+	}
+
+	// If no tag provided, show a picker of the discovered tags.
 	// Track whether we selected the tag interactively to avoid showing a second picker
-	#[allow(non_snake_case)]
-	let (selected_tag, tag_was_interactive) = match tag {
-		Some(t) => (t.to_owned(), false),
+	let (selected_tag, state, tag_was_interactive) = match tag {
+		Some(t) => (t.to_owned(), scan.collect_matching(t), false),
 		None => {
-			if auto_yes {
-				anyhow::bail!(
-					"tag argument is required when using --yes; run without --yes to select interactively"
-				);
-			}
-			(
-				select_tag_interactively(&repo, &worktree, &root, attribute)?,
-				true,
-			)
+			let (tag, state) = select_matching_tag(&scan, &root, attribute)?;
+			(tag, state, true)
 		}
 	};
 
-	let state = attributes::collect_matching_files(&repo, &worktree, &selected_tag, attribute)?;
-
 	if state.matches.is_empty() {
+		if let Some(message) = empty_scan_message(&scan, &root, attribute) {
+			anyhow::bail!(message);
+		}
 		anyhow::bail!(
 			"no matching attribute entries found for tag '{}' in {}",
 			selected_tag,
@@ -44,25 +147,35 @@ pub fn run(
 		);
 	}
 
+	if size {
+		report_size(&repo, &state.patterns)?;
+	}
+	if let Some(path) = manifest {
+		let tags = [selected_tag.clone()];
+		write_manifest(path, &root, attribute, &tags, &state)?;
+	}
+
 	// Skip the preview picker if:
 	// - auto_yes is set, OR
 	// - the tag was already selected interactively (user already made their choice)
+	let patterns = output_patterns(&scan, state.patterns, compact);
 	if auto_yes || tag_was_interactive {
-		for pattern in &state.patterns {
-			println!("{}", pattern);
-		}
-		return Ok(());
+		return print_patterns(
+			print_tag.then_some(selected_tag.as_str()),
+			&patterns,
+			print0,
+			counts,
+		);
 	}
 
 	// Show preview picker only when tag was provided via CLI (let user confirm/browse)
-	let patterns: Vec<String> = state.patterns.into_iter().collect();
 	let picker_attributes = state
 		.tag_counts
 		.into_iter()
 		.map(|(name, count)| picker::AttributeRow::new(name, count))
 		.collect();
 	let files = state
-		.file_map
+		.file_tags
 		.into_iter()
 		.map(|(path, tags)| picker::FileRow::new(path, tags))
 		.collect();
@@ -71,33 +184,340 @@ pub fn run(
 		.with_context(root.display().to_string())
 		.with_initial_query(&selected_tag)
 		.with_attributes(picker_attributes)
-		.with_files(files);
+		.with_files(files)
+		.with_files_first(true);
 
 	let outcome = picker::SearchUi::new(data)
 		.with_ui_config(picker::UiConfig::tags_and_files())
 		.run()?;
 
-	if !outcome.accepted {
+	if outcome.status == picker::PickStatus::Aborted {
 		anyhow::bail!("aborted by user");
 	}
 
-	for pattern in patterns {
-		println!("{}", pattern);
+	print_patterns(
+		print_tag.then_some(selected_tag.as_str()),
+		&patterns,
+		print0,
+		counts,
+	)?;
+
+	Ok(())
+}
+
+/// Why `scan` can't select anything, when the repository has no files or no
+/// `.gitattributes` at all.
+fn empty_scan_message(
+	scan: &attributes::AttributeScan,
+	root: &Path,
+	attribute: &str,
+) -> Option<String> {
+	if scan.indexed_files == 0 {
+		Some(format!(
+			"repository at {} has no indexed files; commit files and add .gitattributes first",
+			root.display()
+		))
+	} else if scan.attribute_files == 0 {
+		Some(no_attribute_files_message(root, attribute))
+	} else {
+		None
 	}
+}
 
+/// Explain how to tag files when a repository has no `.gitattributes` at all.
+fn no_attribute_files_message(root: &Path, attribute: &str) -> String {
+	format!(
+		"no .gitattributes files found in {}\n\n\
+		 Tag files by committing a .gitattributes file that sets the '{}' attribute, e.g.:\n\
+		 \n  assets/project-a/** {}=project-a\n  assets/shared/** {}=global",
+		root.display(),
+		attribute,
+		attribute,
+		attribute
+	)
+}
+
+/// Print `patterns` to stdout, each followed by a newline, or by NUL for `--print0`.
+///
+/// With `--print-tag` the selected `tag` is printed first, as its own record. With `--counts`
+/// each pattern is preceded by the number of matched files it covers and a tab.
+fn print_patterns(
+	tag: Option<&str>,
+	patterns: &BTreeMap<String, usize>,
+	print0: bool,
+	counts: bool,
+) -> Result<()> {
+	let terminator = if print0 { b'\0' } else { b'\n' };
+	let mut stdout = io::stdout().lock();
+	if let Some(tag) = tag {
+		stdout.write_all(tag.as_bytes())?;
+		stdout.write_all(&[terminator])?;
+	}
+	for (pattern, count) in patterns {
+		if counts {
+			write!(stdout, "{count}\t")?;
+		}
+		stdout.write_all(pattern.as_bytes())?;
+		stdout.write_all(&[terminator])?;
+	}
+	stdout.flush()?;
 	Ok(())
 }
 
-/// Discover all available tags in the repository and show a picker for selection.
-#[allow(non_snake_case)]
+/// The patterns to print, compacted into directory patterns when asked, each with the number
+/// of matched files it covers.
+fn output_patterns(
+	scan: &attributes::AttributeScan,
+	patterns: BTreeSet<String>,
+	compact: bool,
+) -> BTreeMap<String, usize> {
+	if compact {
+		scan.compact_counts(&patterns)
+	} else {
+		patterns.into_iter().map(|pattern| (pattern, 1)).collect()
+	}
+}
+
+/// Version of the `--manifest` JSON layout; bump it on incompatible changes.
+const MANIFEST_SCHEMA: u32 = 1;
+
+/// The `--manifest` document: the requested tags and every matched file with all its tags.
+#[derive(Serialize)]
+struct Manifest<'a> {
+	schema: u32,
+	root: String,
+	attribute: &'a str,
+	tags: &'a [String],
+	/// Number of matched files per tag.
+	tag_counts: &'a BTreeMap<String, usize>,
+	files: Vec<ManifestFile<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestFile<'a> {
+	path: &'a str,
+	/// Every tag the file carries.
+	tags: &'a [String],
+	/// The tags that selected it.
+	matched: &'a BTreeSet<String>,
+}
+
+/// Atomically write the selection in `state` as a JSON manifest.
+fn write_manifest(
+	path: &Path,
+	root: &Path,
+	attribute: &str,
+	tags: &[String],
+	state: &attributes::CollectState,
+) -> Result<()> {
+	let manifest = Manifest {
+		schema: MANIFEST_SCHEMA,
+		root: root.display().to_string(),
+		attribute,
+		tags,
+		tag_counts: &state.tag_counts,
+		files: state
+			.file_map
+			.iter()
+			.map(|(file, matched)| ManifestFile {
+				path: file,
+				tags: state
+					.file_tags
+					.get(file)
+					.map(Vec::as_slice)
+					.unwrap_or_default(),
+				matched,
+			})
+			.collect(),
+	};
+
+	let mut json = serde_json::to_string_pretty(&manifest)?;
+	json.push('\n');
+	atomic::write(path, json)?;
+	output::note(&format!("Wrote manifest to {}", path.display()));
+	Ok(())
+}
+
+/// Print the estimated checkout size of `patterns` to stderr.
+fn report_size(repo: &gix::Repository, patterns: &BTreeSet<String>) -> Result<()> {
+	let worktree = git::require_worktree(repo).context("--size reads the checked-out index")?;
+	let estimate = git::size::estimate(repo, &worktree, patterns)?;
+
+	output::divider();
+	output::label_value(
+		"Estimated size",
+		format!(
+			"{} in {} files",
+			output::human_size(estimate.total),
+			estimate.files
+		),
+	);
+	output::bullet_list(
+		estimate
+			.by_top_level
+			.iter()
+			.map(|(dir, bytes)| format!("{}: {}", dir, output::human_size(*bytes))),
+	);
+	output::divider();
+	Ok(())
+}
+
+/// Pick tags in a multi-select picker with the tags already satisfied by an existing sparse
+/// checkout marked, so they can be kept as they are or adjusted.
+///
+/// A tag counts as satisfied when `source`'s `info/sparse-checkout` checks out every file it
+/// selects. With `auto_yes` the satisfied tags are used as they are.
+fn select_tags_from_sparse(
+	scan: &attributes::AttributeScan,
+	root: &Path,
+	attribute: &str,
+	source: &Path,
+	auto_yes: bool,
+) -> Result<Vec<String>> {
+	let git_dir = if source.join("info/sparse-checkout").is_file() {
+		source.to_path_buf()
+	} else {
+		git::open_repository(Some(source))?
+			.0
+			.git_dir()
+			.to_path_buf()
+	};
+	let current = git::sparse::read_patterns(&git_dir)?;
+	if current.is_empty() {
+		anyhow::bail!("no sparse-checkout patterns found in {}", git_dir.display());
+	}
+
+	let covered = scan.tags_covered_by(&current);
+	output::note(&format!(
+		"{} tag(s) fully covered by {} sparse pattern(s): {}",
+		covered.len(),
+		current.len(),
+		covered.join(", ")
+	));
+
+	if auto_yes {
+		if covered.is_empty() {
+			anyhow::bail!("no tag's files are all present in the current sparse checkout");
+		}
+		return Ok(covered);
+	}
+
+	let tag_counts = scan.tag_counts().into_inner();
+	let marked = covered.iter().map(|name| {
+		picker::SearchSelection::Attribute(picker::AttributeRow::new(
+			name.as_str(),
+			tag_counts[name],
+		))
+	});
+	let outcome = picker::SearchUi::new(tag_search_data(scan, root, attribute)?)
+		.with_input_title("Select project tags (Tab to mark)")
+		.with_ui_config(picker::UiConfig::tags_and_files())
+		.with_multi_select(true)
+		.with_marked(marked)
+		.run()?;
+
+	if outcome.status == picker::PickStatus::Aborted {
+		anyhow::bail!("aborted by user");
+	}
+	if outcome.selections.is_empty() {
+		return Ok(vec![accepted_query(&outcome.query)?]);
+	}
+	outcome
+		.selections
+		.into_iter()
+		.map(|selection| match selection {
+			picker::SearchSelection::Attribute(attr) => Ok(attr.name),
+			picker::SearchSelection::File(_) => {
+				anyhow::bail!("unexpected file selection; please select a tag")
+			}
+		})
+		.collect()
+}
+
+/// Read newline-separated tags from stdin, skipping blank lines.
+fn read_stdin_tags() -> Result<Vec<String>> {
+	let mut tags = Vec::new();
+	for line in io::stdin().lock().lines() {
+		let line = line?;
+		let tag = line.trim();
+		if !tag.is_empty() {
+			tags.push(tag.to_owned());
+		}
+	}
+
+	if tags.is_empty() {
+		anyhow::bail!("--stdin was given but no tags were read from stdin");
+	}
+	Ok(tags)
+}
+
+/// Pick a tag interactively, re-prompting while the chosen tag matches no tagged files.
+///
+/// `global` files match every tag, so only tag-specific matches count here.
+fn select_matching_tag(
+	scan: &attributes::AttributeScan,
+	root: &Path,
+	attribute: &str,
+) -> Result<(String, attributes::CollectState)> {
+	let mut initial_query = None;
+	loop {
+		let tag = select_tag_interactively(scan, root, attribute, initial_query.as_deref())?;
+		let state = scan.collect_matching(&tag);
+		if tag == "global" || state.tag_counts.keys().any(|name| name != "global") {
+			return Ok((tag, state));
+		}
+
+		output::warn(&format!("Tag '{}' matches no files", tag));
+		let available: Vec<String> = scan
+			.tag_counts()
+			.into_inner()
+			.into_keys()
+			.filter(|name| name != "global")
+			.collect();
+		output::note(&format!("Available tags: {}", available.join(", ")));
+		initial_query = Some(tag);
+	}
+}
+
+/// Show a picker of all tags found by the repository scan.
 fn select_tag_interactively(
-	repo: &gix::Repository,
-	worktree: &gix::Worktree<'_>,
+	scan: &attributes::AttributeScan,
 	root: &Path,
 	attribute: &str,
+	initial_query: Option<&str>,
 ) -> Result<String> {
-	let tag_counts = attributes::discover_all_tags(repo, worktree, attribute)?;
+	let mut data = tag_search_data(scan, root, attribute)?;
+	if let Some(query) = initial_query {
+		data = data.with_initial_query(query);
+	}
+
+	let outcome = picker::SearchUi::new(data)
+		.with_input_title("Select a project tag")
+		.with_ui_config(picker::UiConfig::tags_and_files())
+		.run()?;
+
+	match (outcome.status, outcome.selection) {
+		(picker::PickStatus::Aborted, _) => anyhow::bail!("aborted by user"),
+		(_, Some(picker::SearchSelection::Attribute(attr))) => Ok(attr.name),
+		(_, Some(picker::SearchSelection::File(_))) => {
+			anyhow::bail!("unexpected file selection; please select a tag")
+		}
+		(_, None) => accepted_query(&outcome.query),
+	}
+}
+
+/// The picker rows for every tag found by the repository scan, or an error explaining why
+/// there are none.
+fn tag_search_data(
+	scan: &attributes::AttributeScan,
+	root: &Path,
+	attribute: &str,
+) -> Result<picker::SearchData> {
+	let tag_counts = scan.tag_counts();
 
+	if let Some(message) = empty_scan_message(scan, root, attribute) {
+		anyhow::bail!(message);
+	}
 	if tag_counts.is_empty() {
 		anyhow::bail!(
 			"no '{}' attributes found in {}; ensure .gitattributes files define the '{}' attribute",
@@ -113,30 +533,16 @@ fn select_tag_interactively(
 		.map(|(name, count)| picker::AttributeRow::new(name, count))
 		.collect();
 
-	let data = picker::SearchData::new()
+	Ok(picker::SearchData::new()
 		.with_context(root.display().to_string())
-		.with_attributes(picker_attributes);
-
-	let outcome = picker::SearchUi::new(data)
-		.with_input_title("Select a project tag")
-		.with_ui_config(picker::UiConfig::tags_and_files())
-		.run()?;
-
-	if !outcome.accepted {
-		anyhow::bail!("aborted by user");
-	}
+		.with_attributes(picker_attributes))
+}
 
-	match outcome.selection {
-		Some(picker::SearchSelection::Attribute(attr)) => Ok(attr.name),
-		Some(picker::SearchSelection::File(_)) => {
-			anyhow::bail!("unexpected file selection; please select a tag")
-		}
-		None => {
-			// User typed a custom query without selecting an item - use the query as the tag
-			if outcome.query.trim().is_empty() {
-				anyhow::bail!("no tag selected");
-			}
-			Ok(outcome.query.trim().to_owned())
-		}
+/// The tag typed into the picker when the query is accepted (Alt+Enter) without selecting
+/// an item.
+fn accepted_query(query: &str) -> Result<String> {
+	if query.trim().is_empty() {
+		anyhow::bail!("no tag selected");
 	}
+	Ok(query.trim().to_owned())
 }
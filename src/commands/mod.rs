@@ -1,3 +1,4 @@
 pub mod generate;
 pub mod setup;
 pub mod teardown;
+pub mod update;
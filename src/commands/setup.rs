@@ -1,18 +1,34 @@
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use gix::bstr::ByteSlice;
 
 use crate::config::Config;
-use crate::git::{self, attributes, config as git_config, git, lfs, sparse, submodule};
+use crate::git::{self, attributes, config as git_config, lfs, sparse, submodule};
 use crate::output;
 
-pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
+pub fn run(
+	config_dir: Option<&Path>,
+	auto_yes: bool,
+	verify: bool,
+	attribute: Option<&str>,
+	backup: bool,
+) -> Result<()> {
 	let config_dir = config_dir.unwrap_or_else(|| Path::new("."));
-	let config = Config::load(config_dir)?;
+	let mut config = Config::load(config_dir)?;
+	if let Some(attribute) = attribute {
+		config.attribute = attribute.to_owned();
+	}
+
+	if config.submodule_branch.trim().is_empty() {
+		output::note("Resolving default branch from remote...");
+		config.submodule_branch = resolve_default_branch(&config)?;
+	}
 
 	// Generate sparse patterns first
+	output::note("Generating sparse patterns...");
 	let sparse_patterns = generate_sparse_patterns(&config)?;
 
 	output::divider();
@@ -23,6 +39,7 @@ pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
 	output::label_value("URL", &config.submodule_url);
 	output::label_value("Branch", &config.submodule_branch);
 	output::label_value("Project Tag", &config.project_tag);
+	output::label_value("Attribute", &config.attribute);
 	output::label_value("Sparse Patterns", sparse_patterns.len());
 	if let Some(mirror) = &config.shared_mirror_path {
 		output::label_value("Mirror", mirror.display());
@@ -35,15 +52,137 @@ pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
 		anyhow::bail!("aborted by user");
 	}
 
+	output::note(&format!(
+		"Setting up submodule in {}...",
+		config.work_repo.display()
+	));
+	let report = execute(
+		&config,
+		SetupOptions {
+			verify,
+			backup,
+			patterns: Some(sparse_patterns),
+		},
+	)?;
+
+	for warning in &report.warnings {
+		output::warn(warning);
+	}
+	if report.gitmodules_changed {
+		output::success("✓ Updated .gitmodules");
+	}
+	if report.git_config_changed {
+		output::success("✓ Updated local git configuration");
+	}
+	if report.gitlink_created {
+		output::success(&format!("✓ Added gitlink to index ({})", report.commit_sha));
+	} else {
+		output::note(&format!(
+			"Gitlink already exists in index ({})",
+			report.commit_sha
+		));
+	}
+	output::success(&format!(
+		"✓ Set up modules directory: {}",
+		report.modules_path.display()
+	));
+	output::success(&format!(
+		"✓ Configured sparse checkout ({} patterns)",
+		report.patterns.len()
+	));
+	if report.materialized_files == 0 && !report.patterns.is_empty() {
+		// git exits successfully even when no pattern matches, leaving an empty worktree.
+		output::warn(&format!(
+			"Sparse checkout wrote no files: none of the {} pattern(s) match anything at {}",
+			report.patterns.len(),
+			report.commit_sha
+		));
+		output::note(
+			"The patterns may be stale (e.g. files were renamed or moved); regenerate them with \
+			 `git sparta generate-sparse-list` and run setup again.",
+		);
+	} else {
+		output::success(&format!(
+			"✓ Materialized {} sparse file(s)",
+			report.materialized_files
+		));
+	}
+	if report.lfs_checked_out {
+		output::success("✓ LFS objects fetched and checked out");
+	}
+	if verify {
+		report_missing_files(&report.missing_files);
+	}
+
+	output::divider();
+	output::success(&format!(
+		"✓ Submodule '{}' successfully set up with sparse checkout!",
+		config.submodule_name
+	));
+	output::note(&format!(
+		"Working tree: {}",
+		config.submodule_path.display()
+	));
+
+	Ok(())
+}
+
+/// Options for [`execute`].
+#[derive(Debug, Clone, Default)]
+pub struct SetupOptions {
+	/// Check that every file named by a sparse pattern exists after checkout.
+	pub verify: bool,
+	/// Keep `.bak` copies of `.gitmodules` and the local git config before editing them.
+	pub backup: bool,
+	/// Sparse patterns to apply; generated from the configuration when `None`.
+	pub patterns: Option<Vec<String>>,
+}
+
+/// What [`execute`] changed.
+#[derive(Debug, Clone, Default)]
+pub struct SetupReport {
+	pub gitmodules_changed: bool,
+	pub git_config_changed: bool,
+	/// Whether a new gitlink was added to the index, rather than an existing one reused.
+	pub gitlink_created: bool,
+	/// Commit the submodule was checked out at.
+	pub commit_sha: String,
+	/// The submodule's git directory under `.git/modules`.
+	pub modules_path: PathBuf,
+	/// Sparse-checkout patterns that were applied.
+	pub patterns: Vec<String>,
+	pub lfs_checked_out: bool,
+	/// Files named by a pattern but missing after checkout; always empty unless verifying.
+	pub missing_files: Vec<PathBuf>,
+	/// Number of files in the submodule's working tree after checkout.
+	pub materialized_files: usize,
+	/// Problems that did not stop the setup, e.g. a mirror without objects or a missing
+	/// git-lfs.
+	pub warnings: Vec<String>,
+}
+
+/// Set up the sparse submodule described by `config` without prompting.
+///
+/// This is the embeddable counterpart of [`run`]: an empty `submodule_branch` is resolved
+/// from the remote, and the outcome, warnings included, is returned instead of printed.
+pub fn execute(config: &Config, options: SetupOptions) -> Result<SetupReport> {
+	let mut warnings = Vec::new();
+	let mut config = config.clone();
+	if config.submodule_branch.trim().is_empty() {
+		config.submodule_branch = resolve_default_branch(&config)?;
+	}
+	let patterns = match options.patterns {
+		Some(patterns) => patterns,
+		None => generate_sparse_patterns(&config)?,
+	};
+
 	// Open the current repository (which might be a submodule itself)
-	let (repo, repo_root) = git::open_repository(Some(&config.work_repo))?;
+	let (repo, _) = git::open_repository(Some(&config.work_repo))?;
 	let git_dir = repo.git_dir().to_path_buf();
 
-	output::note(&format!("Working in repository: {}", repo_root.display()));
-	output::note(&format!("Git directory: {}", git_dir.display()));
-
 	// Update .gitmodules and local git config using shared config module
-	let submodule_cfg = git_config::SubmoduleConfig::new(&config.submodule_name);
+	let submodule_cfg =
+		git_config::SubmoduleConfig::new(&config.submodule_name).with_backup(options.backup);
 
 	let gitmodules_changed = submodule_cfg.ensure_gitmodules(
 		&config.work_repo.join(".gitmodules"),
@@ -58,107 +197,83 @@ pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
 		&config.submodule_branch,
 	)?;
 
-	if gitmodules_changed {
-		output::success("✓ Updated .gitmodules");
-	}
-	if git_config_changed {
-		output::success("✓ Updated local git configuration");
-	}
-
 	// Calculate the modules path
 	let modules_path = git_dir
 		.join("modules")
 		.join(&config.submodule_path_relative);
 
 	// Check if gitlink already exists in index
-	let gitlink_exists = check_gitlink_exists(&repo, &config.submodule_path_relative)?;
+	let gitlink_created = !check_gitlink_exists(&repo, &config.submodule_path_relative)?;
 
-	if !gitlink_exists {
-		output::note("Creating gitlink in index...");
+	if gitlink_created {
 		let commit_sha = fetch_commit_sha(&config)?;
 		add_gitlink(&repo, &config.submodule_path_relative, &commit_sha)?;
-		output::success("✓ Added gitlink to index");
-	} else {
-		output::note("Gitlink already exists in index");
 	}
 
 	// Initialize the submodule metadata
-	output::note("Initializing submodule metadata...");
 	git_submodule_init(&config.work_repo, &config.submodule_path_relative)?;
-	output::success("✓ Submodule initialized");
 
 	// Create the working tree directory
 	fs::create_dir_all(&config.submodule_path)
 		.with_context(|| format!("failed to create {}", config.submodule_path.display()))?;
 
 	// Set up the modules directory (the actual .git directory for the submodule)
-	setup_modules_directory(&modules_path, &config)?;
-	output::success(&format!(
-		"✓ Set up modules directory: {}",
-		modules_path.display()
-	));
+	setup_modules_directory(&modules_path, &config, &mut warnings)?;
 
 	// Create the .git file in the submodule working tree
 	let relative_modules = pathdiff::diff_paths(&modules_path, &config.submodule_path)
 		.context("failed to compute relative path to modules directory")?;
 	let gitfile_content = format!("gitdir: {}\n", relative_modules.display());
 	fs::write(config.submodule_path.join(".git"), gitfile_content)?;
-	output::success("✓ Created .git file in submodule working tree");
 
 	// Configure core.bare and core.worktree
 	configure_modules_repo(&modules_path, &config.submodule_path)?;
-	output::success("✓ Configured modules repository");
 
 	// Add remote if it doesn't exist
 	add_remote_if_missing(&modules_path, &config.submodule_url)?;
 
 	// Fetch the commit
-	fetch_to_modules(&modules_path, &config, gitlink_exists)?;
-	output::success("✓ Fetched remote content");
+	let commit_sha = fetch_to_modules(&modules_path, &config)?;
 
-	// Set up sparse checkout
-	setup_sparse_checkout(&modules_path, &sparse_patterns)?;
-	output::success(&format!(
-		"✓ Configured sparse checkout ({} patterns)",
-		sparse_patterns.len()
-	));
-
-	// Materialize the sparse files
+	// Set up sparse checkout and materialize the sparse files
+	setup_sparse_checkout(&modules_path, &patterns)?;
 	materialize_sparse_files(&modules_path, &config.submodule_path)?;
-	output::success("✓ Materialized sparse files");
 
 	// Handle LFS if the repository uses it
-	if repo_uses_lfs(&config.submodule_path) {
-		fetch_lfs_objects(&modules_path, &config.submodule_path)?;
-		output::success("✓ LFS objects fetched and checked out");
-	}
+	let lfs_checked_out = repo_uses_lfs(&config.submodule_path)
+		&& fetch_lfs_objects(&modules_path, &config.submodule_path, &mut warnings)?;
 
-	output::divider();
-	output::success(&format!(
-		"✓ Submodule '{}' successfully set up with sparse checkout!",
-		config.submodule_name
-	));
-	output::note(&format!(
-		"Working tree: {}",
-		config.submodule_path.display()
-	));
-
-	Ok(())
+	let missing_files = if options.verify {
+		sparse::missing_files(&config.submodule_path, &patterns)
+	} else {
+		Vec::new()
+	};
+	let materialized_files = sparse::materialized_files(&config.submodule_path)
+		.with_context(|| format!("failed to read {}", config.submodule_path.display()))?;
+	// Separate steps can report the same unusable mirror or missing git-lfs; keep the first.
+	let mut seen = HashSet::new();
+	warnings.retain(|warning| seen.insert(warning.clone()));
+
+	Ok(SetupReport {
+		gitmodules_changed,
+		git_config_changed,
+		gitlink_created,
+		commit_sha,
+		modules_path,
+		patterns,
+		lfs_checked_out,
+		missing_files,
+		materialized_files,
+		warnings,
+	})
 }
 
-fn generate_sparse_patterns(config: &Config) -> Result<Vec<String>> {
-	output::note("Generating sparse patterns...");
-
+pub(crate) fn generate_sparse_patterns(config: &Config) -> Result<Vec<String>> {
 	// Use the mirror if available, otherwise use the local submodule path
-	let repo_path = if let Some(mirror) = &config.shared_mirror_path {
-		mirror.clone()
-	} else {
-		config.submodule_path.clone()
-	};
+	let repo_path = config.attributes_repo_path();
 
-	// Check if the path is a git repository (either .git directory or .git file for worktrees)
-	let git_path = repo_path.join(".git");
-	if !git_path.exists() {
+	// Accept a .git directory, a .git file (worktrees) or a bare repository
+	if !git::repository::is_repository(repo_path) {
 		if config.shared_mirror_path.is_some() {
 			anyhow::bail!(
 				"No git repository found at mirror path: {}\n\
@@ -182,20 +297,50 @@ fn generate_sparse_patterns(config: &Config) -> Result<Vec<String>> {
 		}
 	}
 
-	let (repo, _) = git::open_repository(Some(&repo_path))?;
-	let worktree = git::require_worktree(&repo)?;
+	let (repo, _) = git::open_repository(Some(repo_path))?;
 
-	// Use the shared attributes module to collect sparse patterns
-	let patterns =
-		attributes::collect_sparse_patterns(&repo, &worktree, &config.project_tag, "projects")?;
+	// Use the shared attributes module to collect sparse patterns; a bare mirror is scanned
+	// from its HEAD tree.
+	let options = config.project.pattern_options();
+	let scan = attributes::scan_repository_with(&repo, &config.attribute, &options)?;
+	matching_patterns(config, &scan)
+}
 
+/// Sparse patterns for `config`'s tag as of `commit` in the repository at `git_dir`, so an
+/// update selects what the fetched commit tags rather than what is currently checked out.
+pub(crate) fn generate_sparse_patterns_at(
+	config: &Config,
+	git_dir: &Path,
+	commit: &str,
+) -> Result<Vec<String>> {
+	let (repo, _) = git::open_repository(Some(git_dir))?;
+	let options = config.project.pattern_options();
+	let scan = attributes::scan_commit_with(&repo, commit, &config.attribute, &options)?;
+	matching_patterns(config, &scan)
+}
+
+fn matching_patterns(config: &Config, scan: &attributes::AttributeScan) -> Result<Vec<String>> {
+	let patterns = scan.collect_matching(&config.project_tag).patterns;
 	if patterns.is_empty() {
-		anyhow::bail!("No patterns found for tag '{}'", config.project_tag);
+		anyhow::bail!(
+			"No patterns found for tag '{}' in attribute '{}'",
+			config.project_tag,
+			config.attribute
+		);
 	}
 
 	Ok(patterns.into_iter().collect())
 }
 
+pub(crate) fn resolve_default_branch(config: &Config) -> Result<String> {
+	git::repository::remote_default_branch(&config.submodule_url).with_context(|| {
+		format!(
+			"SUBMODULE_BRANCH is not set and the default branch of {} could not be determined",
+			config.submodule_url
+		)
+	})
+}
+
 fn check_gitlink_exists(repo: &gix::Repository, submodule_path: &Path) -> Result<bool> {
 	let index = match repo.open_index() {
 		Ok(index) => index,
@@ -217,33 +362,7 @@ fn check_gitlink_exists(repo: &gix::Repository, submodule_path: &Path) -> Result
 }
 
 fn fetch_commit_sha(config: &Config) -> Result<String> {
-	output::note("Fetching commit SHA from remote...");
-
-	// Use a temporary directory for the fetch
-	let temp_dir = tempfile::tempdir()?;
-	let temp_path = temp_dir.path();
-
-	// Initialize a bare repository
-	git::repository::init_bare(temp_path)?;
-
-	// Add remote
-	submodule::add_remote_if_missing(temp_path, "origin", &config.submodule_url)?;
-
-	// Configure alternates if using mirror
-	if let Some(mirror) = &config.shared_mirror_path {
-		submodule::configure_alternates(temp_path, mirror)?;
-	}
-
-	// Fetch
-	submodule::fetch(temp_path, "origin", &config.submodule_branch, Some(1))?;
-
-	// Get the SHA
-	let sha = git()
-		.git_dir(temp_path)
-		.args(["rev-parse", "FETCH_HEAD"])
-		.stdout()?;
-
-	Ok(sha)
+	git::repository::remote_commit(&config.submodule_url, &config.submodule_branch)
 }
 
 fn add_gitlink(repo: &gix::Repository, submodule_path: &Path, commit_sha: &str) -> Result<()> {
@@ -257,8 +376,12 @@ fn git_submodule_init(work_repo: &Path, submodule_path: &Path) -> Result<()> {
 	submodule::init(work_repo, submodule_path)
 }
 
-fn setup_modules_directory(modules_path: &Path, config: &Config) -> Result<()> {
-	submodule::setup_modules_directory(modules_path, config.shared_mirror_path.as_deref())
+fn setup_modules_directory(
+	modules_path: &Path,
+	config: &Config,
+	warnings: &mut Vec<String>,
+) -> Result<()> {
+	submodule::setup_modules_directory(modules_path, config.shared_mirror_path.as_deref(), warnings)
 }
 
 fn configure_modules_repo(modules_path: &Path, worktree_path: &Path) -> Result<()> {
@@ -270,21 +393,22 @@ fn add_remote_if_missing(modules_path: &Path, remote_url: &str) -> Result<()> {
 	Ok(())
 }
 
-fn fetch_to_modules(modules_path: &Path, config: &Config, _gitlink_exists: bool) -> Result<()> {
+/// Make sure the gitlink's commit is present in the modules repository and check it out,
+/// returning its SHA.
+fn fetch_to_modules(modules_path: &Path, config: &Config) -> Result<String> {
 	// Get the commit SHA from the gitlink
 	let commit_sha =
 		submodule::get_gitlink_sha(&config.work_repo, &config.submodule_path_relative)?;
 
 	// Check if we already have the commit
 	if !submodule::has_commit(modules_path, &commit_sha)? {
-		output::note(&format!("Fetching commit {}...", commit_sha));
 		submodule::fetch(modules_path, "origin", &config.submodule_branch, Some(1))?;
 	}
 
 	// Update refs
 	submodule::update_refs(modules_path, &commit_sha, &config.submodule_branch)?;
 
-	Ok(())
+	Ok(commit_sha)
 }
 
 fn setup_sparse_checkout(modules_path: &Path, patterns: &[String]) -> Result<()> {
@@ -295,12 +419,33 @@ fn materialize_sparse_files(modules_path: &Path, worktree_path: &Path) -> Result
 	sparse::checkout(modules_path, worktree_path)
 }
 
+/// Warn about sparse patterns whose file did not end up in the working tree.
+fn report_missing_files(missing: &[PathBuf]) {
+	if missing.is_empty() {
+		output::success("✓ Verified checked-out files");
+		return;
+	}
+
+	output::warn(&format!(
+		"{} sparse pattern(s) have no file in the working tree:",
+		missing.len()
+	));
+	output::bullet_list(missing.iter().map(|path| path.display().to_string()));
+	output::note(
+		"A pattern may not match anything at this commit, or an LFS smudge may have failed.",
+	);
+}
+
 /// Check if the repository uses Git LFS by looking for filter=lfs in .gitattributes
 fn repo_uses_lfs(worktree_path: &Path) -> bool {
 	lfs::is_enabled(worktree_path)
 }
 
 /// Fetch and checkout LFS objects for the sparse checkout
-fn fetch_lfs_objects(modules_path: &Path, worktree_path: &Path) -> Result<()> {
-	lfs::fetch_and_checkout(modules_path, worktree_path)
+fn fetch_lfs_objects(
+	modules_path: &Path,
+	worktree_path: &Path,
+	warnings: &mut Vec<String>,
+) -> Result<bool> {
+	lfs::fetch_and_checkout(modules_path, worktree_path, warnings)
 }
@@ -7,7 +7,7 @@ use crate::config::Config;
 use crate::git::{self, config as git_config};
 use crate::output;
 
-pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
+pub fn run(config_dir: Option<&Path>, auto_yes: bool, backup: bool) -> Result<()> {
 	let config_dir = config_dir.unwrap_or_else(|| Path::new("."));
 	let config = Config::load(config_dir)?;
 
@@ -29,31 +29,70 @@ pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
 		anyhow::bail!("aborted by user");
 	}
 
-	let (repo, _) = git::open_repository(Some(&config.work_repo))?;
-	let git_dir = repo.git_dir().to_path_buf();
-
-	// Use shared config module for git config manipulation
-	let submodule_cfg = git_config::SubmoduleConfig::new(&config.submodule_name);
-
-	let gitmodules_changed =
-		submodule_cfg.remove_from_gitmodules(&config.work_repo.join(".gitmodules"))?;
-	let git_config_changed = submodule_cfg.remove_from_local_config(&git_dir.join("config"))?;
+	let report = execute(&config, TeardownOptions { backup })?;
 
-	if gitmodules_changed {
+	if report.gitmodules_changed {
 		output::success("Removed entry from .gitmodules");
 	}
-	if git_config_changed {
+	if report.git_config_changed {
 		output::success("Removed entry from local git config");
 	}
-
-	if config.submodule_path.exists() {
-		fs::remove_dir_all(&config.submodule_path)
-			.with_context(|| format!("failed to remove {}", config.submodule_path.display()))?;
+	if report.removed_worktree {
 		output::success(&format!(
 			"Deleted working directory {}",
 			config.submodule_path.display()
 		));
 	}
+	if report.removed_modules {
+		output::success("Removed modules repository");
+	}
+
+	output::success(&format!("Submodule '{}' removed", config.submodule_name));
+	output::note("Review git status and stage removals as needed.");
+	Ok(())
+}
+
+/// Options for [`execute`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TeardownOptions {
+	/// Keep `.bak` copies of `.gitmodules` and the local git config before editing them.
+	pub backup: bool,
+}
+
+/// What [`execute`] removed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TeardownReport {
+	pub gitmodules_changed: bool,
+	pub git_config_changed: bool,
+	/// Whether the submodule's working directory was deleted.
+	pub removed_worktree: bool,
+	/// Whether the submodule's git directory under `.git/modules` was deleted.
+	pub removed_modules: bool,
+}
+
+/// Remove the sparse submodule described by `config` without prompting.
+///
+/// This is the embeddable counterpart of [`run`]; the outcome is returned instead of printed.
+pub fn execute(config: &Config, options: TeardownOptions) -> Result<TeardownReport> {
+	let (repo, _) = git::open_repository(Some(&config.work_repo))?;
+	let git_dir = repo.git_dir().to_path_buf();
+
+	// Use shared config module for git config manipulation
+	let submodule_cfg =
+		git_config::SubmoduleConfig::new(&config.submodule_name).with_backup(options.backup);
+
+	let mut report = TeardownReport {
+		gitmodules_changed: submodule_cfg
+			.remove_from_gitmodules(&config.work_repo.join(".gitmodules"))?,
+		git_config_changed: submodule_cfg.remove_from_local_config(&git_dir.join("config"))?,
+		..TeardownReport::default()
+	};
+
+	if config.submodule_path.exists() {
+		fs::remove_dir_all(&config.submodule_path)
+			.with_context(|| format!("failed to remove {}", config.submodule_path.display()))?;
+		report.removed_worktree = true;
+	}
 
 	let modules_path = git_dir
 		.join("modules")
@@ -62,12 +101,10 @@ pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
 		fs::remove_dir_all(&modules_path)
 			.with_context(|| format!("failed to remove {}", modules_path.display()))?;
 		prune_empty_parents(modules_path.parent().unwrap_or(&modules_path), &git_dir)?;
-		output::success("Removed modules repository");
+		report.removed_modules = true;
 	}
 
-	output::success(&format!("Submodule '{}' removed", config.submodule_name));
-	output::note("Review git status and stage removals as needed.");
-	Ok(())
+	Ok(report)
 }
 
 fn prune_empty_parents(start: &Path, git_dir: &Path) -> Result<()> {
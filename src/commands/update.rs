@@ -0,0 +1,250 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::setup;
+use crate::config::Config;
+use crate::git::{self, git, lfs, sparse, submodule};
+use crate::output;
+
+pub fn run(config_dir: Option<&Path>, auto_yes: bool) -> Result<()> {
+	let config_dir = config_dir.unwrap_or_else(|| Path::new("."));
+	let mut config = Config::load(config_dir)?;
+
+	if config.submodule_branch.trim().is_empty() {
+		output::note("Resolving default branch from remote...");
+		config.submodule_branch = setup::resolve_default_branch(&config)?;
+	}
+
+	let (repo, _) = git::open_repository(Some(&config.work_repo))?;
+	let modules_path = repo
+		.git_dir()
+		.join("modules")
+		.join(&config.submodule_path_relative);
+
+	if !modules_path.exists() || !config.submodule_path.exists() {
+		anyhow::bail!(
+			"Submodule '{}' is not set up at {}; run `git sparta setup-submodule` first",
+			config.submodule_name,
+			config.submodule_path.display()
+		);
+	}
+
+	output::divider();
+	output::heading("Submodule update summary");
+	output::label_value("Submodule", &config.submodule_name);
+	output::label_value("Path", config.submodule_path.display());
+	output::label_value("Branch", &config.submodule_branch);
+	output::label_value("Project Tag", &config.project_tag);
+	output::divider();
+
+	if !output::confirm("Fetch and update the submodule?", true, auto_yes)? {
+		anyhow::bail!("aborted by user");
+	}
+
+	output::note(&format!(
+		"Fetching {} and regenerating sparse patterns...",
+		config.submodule_branch
+	));
+	let report = execute(&config, &modules_path)?;
+	output::success(&format!("✓ Updated to {}", report.commit_sha));
+	output::success(&format!(
+		"✓ Materialized sparse files ({} patterns)",
+		report.patterns.len()
+	));
+	for warning in &report.warnings {
+		output::warn(warning);
+	}
+	if report.lfs_checked_out {
+		output::success("✓ LFS objects fetched and checked out");
+	}
+
+	let diff = report.diff;
+	output::divider();
+	if diff.is_empty() {
+		output::note("Sparse patterns unchanged");
+	} else {
+		if !diff.added.is_empty() {
+			output::heading(&format!("Added patterns ({})", diff.added.len()));
+			output::bullet_list(diff.added);
+		}
+		if !diff.removed.is_empty() {
+			output::heading(&format!("Removed patterns ({})", diff.removed.len()));
+			output::bullet_list(diff.removed);
+		}
+	}
+	output::note(
+		"The parent repository's gitlink was not changed; commit it to pin the new revision.",
+	);
+
+	Ok(())
+}
+
+/// What [`execute`] changed.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateReport {
+	/// Commit the submodule now points at.
+	pub commit_sha: String,
+	/// Sparse-checkout patterns that were applied.
+	pub patterns: Vec<String>,
+	/// How `patterns` differ from the ones previously in the sparse-checkout file.
+	pub diff: sparse::PatternDiff,
+	pub lfs_checked_out: bool,
+	/// Problems that did not stop the update, e.g. a missing git-lfs.
+	pub warnings: Vec<String>,
+}
+
+/// Fetch `config.submodule_branch` into the set-up submodule at `modules_path`, then
+/// re-apply the sparse patterns generated from the fetched commit, without prompting.
+pub fn execute(config: &Config, modules_path: &Path) -> Result<UpdateReport> {
+	submodule::fetch(modules_path, "origin", &config.submodule_branch, Some(1))?;
+	let commit_sha = git()
+		.git_dir(modules_path)
+		.args(["rev-parse", "FETCH_HEAD"])
+		.stdout()?;
+	submodule::update_refs(modules_path, &commit_sha, &config.submodule_branch)?;
+
+	let previous = sparse::read_patterns(modules_path)?;
+	let patterns = setup::generate_sparse_patterns_at(config, modules_path, &commit_sha)?;
+	let diff = sparse::diff_patterns(&previous, &patterns);
+
+	sparse::configure(modules_path, &patterns)?;
+	sparse::checkout(modules_path, &config.submodule_path)?;
+
+	let mut warnings = Vec::new();
+	let lfs_checked_out = lfs::is_enabled(&config.submodule_path)
+		&& lfs::fetch_and_checkout(modules_path, &config.submodule_path, &mut warnings)?;
+
+	Ok(UpdateReport {
+		commit_sha,
+		patterns,
+		diff,
+		lfs_checked_out,
+		warnings,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::path::PathBuf;
+
+	use super::*;
+	use crate::git::repository;
+
+	fn commit_files(dir: &Path, files: &[(&str, &str)]) {
+		for (path, contents) in files {
+			let full = dir.join(path);
+			fs::create_dir_all(full.parent().unwrap()).unwrap();
+			fs::write(full, contents).unwrap();
+		}
+		git().cwd(dir).args(["add", "-A"]).run().unwrap();
+		git()
+			.cwd(dir)
+			.args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+			.args(["commit", "-q", "-m", "update"])
+			.run()
+			.unwrap();
+	}
+
+	/// An upstream on `trunk` tagging `a/` for `alpha`, and a work repository with `lib` set up
+	/// from it at `a/one.txt`.
+	fn set_up_submodule(dir: &Path) -> (PathBuf, PathBuf, Config) {
+		let upstream = dir.join("upstream");
+		repository::init(&upstream).unwrap();
+		git()
+			.cwd(&upstream)
+			.args(["checkout", "-q", "-b", "trunk"])
+			.run()
+			.unwrap();
+		commit_files(
+			&upstream,
+			&[
+				(".gitattributes", "a/** projects=alpha\n"),
+				("a/one.txt", "one"),
+				("b/two.txt", "two"),
+			],
+		);
+
+		let work = dir.join("work");
+		repository::init(&work).unwrap();
+		fs::write(
+			work.join("sparta.json"),
+			serde_json::json!({
+				"SUBMODULE_NAME": "lib",
+				"SUBMODULE_PATH": "lib",
+				"SUBMODULE_URL": upstream,
+				"SUBMODULE_BRANCH": "trunk",
+				"PROJECT_TAG": "alpha",
+			})
+			.to_string(),
+		)
+		.unwrap();
+		let config = Config::load(&work).unwrap();
+		setup::execute(
+			&config,
+			setup::SetupOptions {
+				patterns: Some(vec!["a/one.txt".to_owned()]),
+				..Default::default()
+			},
+		)
+		.unwrap();
+		assert!(config.submodule_path.join("a/one.txt").is_file());
+		assert!(!config.submodule_path.join("b/two.txt").exists());
+
+		(upstream, work, config)
+	}
+
+	#[test]
+	fn test_update_selects_files_tagged_by_the_fetched_commit() {
+		let dir = tempfile::tempdir().unwrap();
+		let (upstream, work, config) = set_up_submodule(dir.path());
+
+		// The new commit tags `b/` and adds a file there.
+		commit_files(
+			&upstream,
+			&[
+				(
+					".gitattributes",
+					"a/** projects=alpha\nb/** projects=alpha\n",
+				),
+				("b/three.txt", "three"),
+			],
+		);
+		run(Some(&work), true).unwrap();
+
+		let (repo, _) = git::open_repository(Some(&upstream)).unwrap();
+		let modules_path = work.join(".git/modules/lib");
+		assert_eq!(
+			sparse::read_patterns(&modules_path).unwrap(),
+			["a/one.txt", "b/three.txt", "b/two.txt"]
+		);
+		assert!(config.submodule_path.join("b/three.txt").is_file());
+		assert_eq!(
+			git()
+				.git_dir(&modules_path)
+				.args(["rev-parse", "HEAD"])
+				.stdout()
+				.unwrap(),
+			repo.head_id().unwrap().to_string()
+		);
+	}
+
+	#[test]
+	fn test_update_removes_files_no_longer_tagged() {
+		let dir = tempfile::tempdir().unwrap();
+		let (upstream, work, config) = set_up_submodule(dir.path());
+
+		// The new commit drops `a/` from the tag and tags `b/` instead.
+		commit_files(&upstream, &[(".gitattributes", "b/** projects=alpha\n")]);
+		let modules_path = work.join(".git/modules/lib");
+		let report = execute(&config, &modules_path).unwrap();
+
+		assert_eq!(report.patterns, ["b/two.txt"]);
+		assert_eq!(report.diff.added, ["b/two.txt"]);
+		assert_eq!(report.diff.removed, ["a/one.txt"]);
+		assert!(!config.submodule_path.join("a/one.txt").exists());
+		assert!(config.submodule_path.join("b/two.txt").is_file());
+		assert_eq!(sparse::read_patterns(&modules_path).unwrap(), ["b/two.txt"]);
+	}
+}
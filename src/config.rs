@@ -5,6 +5,11 @@ use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 use serde_json::Value;
 
+use crate::project_config::ProjectConfig;
+
+/// Attribute scanned for tags when the configuration doesn't name one.
+pub const DEFAULT_ATTRIBUTE: &str = "projects";
+
 #[derive(Debug, Clone)]
 pub struct Config {
 	pub submodule_name: String,
@@ -13,9 +18,14 @@ pub struct Config {
 	pub submodule_url: String,
 	pub submodule_branch: String,
 	pub project_tag: String,
+	/// Git attribute holding the tags: `ATTRIBUTE`, else the project's `.sparta.toml`, else
+	/// `projects`.
+	pub attribute: String,
 	pub shared_mirror_path: Option<PathBuf>,
 	pub config_file: PathBuf,
 	pub work_repo: PathBuf,
+	/// Project defaults from the `.sparta.toml` above the work repository, if any.
+	pub project: ProjectConfig,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +43,15 @@ impl Config {
 		base.config_file = config_file;
 		base.work_repo = config_dir.clone();
 
+		base.project = ProjectConfig::discover(&config_dir)?;
+		if base.attribute.is_empty() {
+			base.attribute = base
+				.project
+				.attribute
+				.clone()
+				.unwrap_or_else(|| DEFAULT_ATTRIBUTE.to_owned());
+		}
+
 		// Apply local overrides first, then env overrides.
 		let overrides = load_local_overrides(&config_dir)?;
 		apply_overrides(&mut base, &overrides);
@@ -64,6 +83,14 @@ impl Config {
 
 		Ok(base)
 	}
+
+	/// Repository whose `.gitattributes` decide the sparse patterns: the shared mirror if
+	/// configured, otherwise the submodule checkout itself.
+	pub fn attributes_repo_path(&self) -> &Path {
+		self.shared_mirror_path
+			.as_deref()
+			.unwrap_or(&self.submodule_path)
+	}
 }
 
 fn find_base_config(config_dir: &Path) -> Result<(Config, PathBuf)> {
@@ -83,7 +110,6 @@ fn find_base_config(config_dir: &Path) -> Result<(Config, PathBuf)> {
 		"SUBMODULE_NAME",
 		"SUBMODULE_PATH",
 		"SUBMODULE_URL",
-		"SUBMODULE_BRANCH",
 		"PROJECT_TAG",
 	];
 
@@ -98,14 +124,26 @@ fn find_base_config(config_dir: &Path) -> Result<(Config, PathBuf)> {
 				submodule_path: PathBuf::from(get_string(object, "SUBMODULE_PATH")?),
 				submodule_path_relative: PathBuf::new(),
 				submodule_url: get_string(object, "SUBMODULE_URL")?,
-				submodule_branch: get_string(object, "SUBMODULE_BRANCH")?,
+				submodule_branch: object
+					.get("SUBMODULE_BRANCH")
+					.and_then(|v| v.as_str())
+					.unwrap_or_default()
+					.to_owned(),
 				project_tag: get_string(object, "PROJECT_TAG")?,
+				// Empty until `Config::load` falls back to the project config.
+				attribute: object
+					.get("ATTRIBUTE")
+					.and_then(|v| v.as_str())
+					.filter(|s| !s.trim().is_empty())
+					.unwrap_or_default()
+					.to_owned(),
 				shared_mirror_path: object
 					.get("SHARED_MIRROR_PATH")
 					.and_then(|v| v.as_str())
 					.map(PathBuf::from),
 				config_file: candidate.clone(),
 				work_repo: config_dir.to_path_buf(),
+				project: ProjectConfig::default(),
 			};
 			return Ok((config, candidate));
 		}
@@ -3,15 +3,40 @@
 //! This module provides utilities for scanning git repositories and collecting
 //! attribute information, particularly for the "projects" attribute used by git-sparta.
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{Context, Result};
 use gix::attrs::StateRef;
 use gix::bstr::ByteSlice;
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
 
+use super::sparse::SparsePatterns;
 use super::submodule::discover_submodules;
 use crate::git;
 
+/// Characters that separate multiple tags in a single attribute value.
+pub const TAG_SEPARATORS: &[char] = &[',', ';'];
+
+/// Split an attribute value into its trimmed, non-empty tags.
+pub fn split_tags(value: &str) -> impl Iterator<Item = &str> {
+	split_tags_with(value, TAG_SEPARATORS)
+}
+
+/// Split like [`split_tags`], on `separators` instead of [`TAG_SEPARATORS`].
+pub fn split_tags_with<'a>(
+	value: &'a str,
+	separators: &'a [char],
+) -> impl Iterator<Item = &'a str> {
+	value
+		.split(separators)
+		.map(str::trim)
+		.filter(|token| !token.is_empty())
+}
+
 /// Statistics about collected attributes/tags.
 #[derive(Debug, Default)]
 pub struct TagCounts(pub BTreeMap<String, usize>);
@@ -48,6 +73,8 @@ pub struct CollectState {
 	pub tag_counts: BTreeMap<String, usize>,
 	/// Map of pattern -> set of tags.
 	pub file_map: BTreeMap<String, BTreeSet<String>>,
+	/// Map of pattern -> every tag on the file in attribute order, matched or not.
+	pub file_tags: BTreeMap<String, Vec<String>>,
 }
 
 impl CollectState {
@@ -55,179 +82,374 @@ impl CollectState {
 		Self::default()
 	}
 
-	/// Record a match for the given pattern and token.
-	pub fn record_match(&mut self, pattern: &str, token: &str, user_tag: &str) {
-		if token == "global" || token.contains(user_tag) {
-			let pattern_owned = pattern.to_owned();
-			let token_owned = token.to_owned();
-			self.matches
-				.push((pattern_owned.clone(), token_owned.clone()));
-			self.patterns.insert(pattern_owned.clone());
-			*self.tag_counts.entry(token_owned.clone()).or_insert(0) += 1;
-			self.file_map
-				.entry(pattern_owned)
-				.or_default()
-				.insert(token_owned);
-		}
+	/// Record `token` as a match on `pattern`.
+	fn record(&mut self, pattern: &str, token: &str) {
+		let pattern_owned = pattern.to_owned();
+		let token_owned = token.to_owned();
+		self.matches
+			.push((pattern_owned.clone(), token_owned.clone()));
+		self.patterns.insert(pattern_owned.clone());
+		*self.tag_counts.entry(token_owned.clone()).or_insert(0) += 1;
+		self.file_map
+			.entry(pattern_owned)
+			.or_default()
+			.insert(token_owned);
 	}
 }
 
-/// Discover all unique tags/attributes in a repository and its submodules.
+/// Decides whether a file's tag token selects it for the tag the user asked for.
 ///
-/// This traverses the entire repository (and recursively into submodules)
-/// to find all values of the specified attribute.
-pub fn discover_all_tags(
-	repo: &gix::Repository,
-	worktree: &gix::Worktree<'_>,
-	attribute: &str,
-) -> Result<TagCounts> {
-	let mut tag_counts = TagCounts::new();
-	discover_tags_recursive(repo, worktree, "", &mut tag_counts, attribute)?;
-	Ok(tag_counts)
+/// The `global` rule is applied separately by [`AttributeScan`], so a matcher only compares
+/// ordinary tags.
+#[derive(Clone)]
+pub struct TagMatcher(Arc<MatchFn>);
+
+/// `(token, user_tag) -> bool`
+type MatchFn = dyn Fn(&str, &str) -> bool + Send + Sync;
+
+impl TagMatcher {
+	/// Wrap a custom `(token, user_tag) -> bool` predicate.
+	pub fn new(predicate: impl Fn(&str, &str) -> bool + Send + Sync + 'static) -> Self {
+		Self(Arc::new(predicate))
+	}
+
+	/// The token contains the user tag (default), so `alpha` also selects `alpha-extra`.
+	pub fn substring() -> Self {
+		Self::new(|token, user_tag| token.contains(user_tag))
+	}
+
+	/// The token equals the user tag.
+	pub fn exact() -> Self {
+		Self::new(|token, user_tag| token == user_tag)
+	}
+
+	/// The user tag is a glob matched against the whole token, e.g. `alpha-*`.
+	///
+	/// An invalid glob matches nothing.
+	pub fn glob() -> Self {
+		let compiled: Mutex<HashMap<String, Option<GlobMatcher>>> = Mutex::default();
+		Self::new(move |token, user_tag| {
+			let mut compiled = compiled.lock().unwrap_or_else(|e| e.into_inner());
+			compiled
+				.entry(user_tag.to_owned())
+				.or_insert_with(|| Glob::new(user_tag).ok().map(|g| g.compile_matcher()))
+				.as_ref()
+				.is_some_and(|matcher| matcher.is_match(token))
+		})
+	}
+
+	pub fn matches(&self, token: &str, user_tag: &str) -> bool {
+		(self.0)(token, user_tag)
+	}
 }
 
-fn discover_tags_recursive(
-	repo: &gix::Repository,
-	worktree: &gix::Worktree<'_>,
-	prefix: &str,
-	tag_counts: &mut TagCounts,
-	attribute: &str,
-) -> Result<()> {
-	let base_display = worktree.base().display().to_string();
-	let mut attr_stack = worktree
-		.attributes(None)
-		.with_context(|| format!("failed to load git attribute stack for {}", base_display))?;
-	let mut outcome = attr_stack.selected_attribute_matches([attribute]);
+impl Default for TagMatcher {
+	fn default() -> Self {
+		Self::substring()
+	}
+}
 
-	let index = repo.open_index().with_context(|| {
-		format!(
-			"failed to load git index for repository at {}",
-			base_display
-		)
-	})?;
+/// Named [`TagMatcher`] strategies, as chosen on the command line or in `.sparta.toml`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+	/// The file's tag contains the given tag.
+	#[default]
+	Substring,
+	/// The file's tag equals the given tag.
+	Exact,
+	/// The given tag is a glob matched against the file's tag.
+	Glob,
+}
 
-	let mut processed_submodules: BTreeSet<String> = BTreeSet::new();
+impl MatchMode {
+	pub fn matcher(self) -> TagMatcher {
+		match self {
+			Self::Substring => TagMatcher::substring(),
+			Self::Exact => TagMatcher::exact(),
+			Self::Glob => TagMatcher::glob(),
+		}
+	}
+}
 
-	for entry in index.entries() {
-		let path = entry.path(&index);
-		let path_display = path.to_str_lossy();
-		let local_path = path_display.as_ref();
+impl fmt::Debug for TagMatcher {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("TagMatcher").finish_non_exhaustive()
+	}
+}
 
-		if entry.mode == gix::index::entry::Mode::COMMIT {
-			processed_submodules.insert(local_path.to_owned());
-			let submodule_worktree_path = worktree.base().join(local_path);
-			if !submodule_worktree_path.exists() {
-				continue;
-			}
+/// Every file in a repository (and its submodules) that sets the scanned attribute, with the
+/// attribute's tokens.
+///
+/// A single scan can answer both "which tags exist" and "which files match a tag", so callers
+/// that need both don't have to walk the index twice.
+#[derive(Debug)]
+pub struct AttributeScan {
+	/// Map of pattern -> tokens, in attribute order. A set-without-value attribute yields
+	/// the `global` token.
+	pub files: BTreeMap<String, Vec<String>>,
+	/// Number of `.gitattributes` files seen in the scanned indexes.
+	pub attribute_files: usize,
+	/// Number of files (not gitlinks) in the scanned indexes, before any path filter. Zero
+	/// means nothing has been committed or staged yet.
+	pub indexed_files: usize,
+	/// Map of directory (no trailing `/`) -> number of index entries below it, files and
+	/// gitlinks alike, before any path filter.
+	pub dir_entries: BTreeMap<String, usize>,
+	/// Whether a set-without-value attribute is the `global` tag that matches every tag.
+	/// When false such attributes are ignored and `global` is an ordinary tag.
+	pub treat_set_as_global: bool,
+	/// Compares tokens with the requested tags.
+	pub matcher: TagMatcher,
+	/// Characters that separate tags in an attribute value.
+	pub separators: Vec<char>,
+}
 
-			let (sub_repo, _) =
-				git::open_repository(Some(&submodule_worktree_path)).with_context(|| {
-					format!(
-						"failed to open submodule at {}",
-						submodule_worktree_path.display()
-					)
-				})?;
-			let sub_worktree = git::require_worktree(&sub_repo).with_context(|| {
-				format!(
-					"submodule at {} is bare; a worktree is required for this operation",
-					submodule_worktree_path.display()
-				)
-			})?;
+impl Default for AttributeScan {
+	fn default() -> Self {
+		Self {
+			files: BTreeMap::new(),
+			attribute_files: 0,
+			indexed_files: 0,
+			dir_entries: BTreeMap::new(),
+			treat_set_as_global: true,
+			matcher: TagMatcher::default(),
+			separators: TAG_SEPARATORS.to_vec(),
+		}
+	}
+}
 
-			let next_prefix = if prefix.is_empty() {
-				local_path.to_owned()
-			} else {
-				format!("{}/{}", prefix, local_path)
-			};
+impl AttributeScan {
+	/// An empty scan configured by `options`.
+	fn with_options(options: &PatternOptions) -> Self {
+		Self {
+			treat_set_as_global: options.treat_set_as_global,
+			matcher: options.matcher.clone(),
+			separators: options.separators.clone(),
+			..Self::default()
+		}
+	}
 
-			discover_tags_recursive(
-				&sub_repo,
-				&sub_worktree,
-				&next_prefix,
-				tag_counts,
-				attribute,
-			)?;
-			continue;
+	/// Count how many files carry each tag.
+	pub fn tag_counts(&self) -> TagCounts {
+		let mut tag_counts = TagCounts::new();
+		for token in self.files.values().flatten() {
+			tag_counts.record(token);
 		}
+		tag_counts
+	}
 
-		let platform = attr_stack
-			.at_entry(path, Some(entry.mode))
-			.with_context(|| {
-				format!(
-					"failed to evaluate attributes for {}",
-					if prefix.is_empty() {
-						local_path.to_owned()
-					} else {
-						format!("{}/{}", prefix, local_path)
-					}
-				)
-			})?;
+	/// Collect the files matching `tag` (plus `global` files).
+	pub fn collect_matching(&self, tag: &str) -> CollectState {
+		self.collect_matching_any(&[tag])
+	}
 
-		if platform.matching_attributes(&mut outcome)
-			&& let Some(attr_state) = outcome.iter_selected().next().map(|m| m.assignment.state)
-		{
-			match attr_state {
-				StateRef::Unspecified | StateRef::Unset => {}
-				StateRef::Set => {
-					tag_counts.record("global");
-				}
-				StateRef::Value(value) => {
-					let raw = value.as_bstr().to_str_lossy();
-					for token in raw
-						.split(',')
-						.map(|token| token.trim())
-						.filter(|s| !s.is_empty())
-					{
-						tag_counts.record(token);
-					}
+	/// Collect the files matching any of `tags` (plus `global` files).
+	///
+	/// Each matching token is recorded once, however many of the tags it matches. Every
+	/// selected file also gets its full tag list in [`CollectState::file_tags`].
+	pub fn collect_matching_any<S: AsRef<str>>(&self, tags: &[S]) -> CollectState {
+		let mut state = CollectState::new();
+		for (pattern, tokens) in &self.files {
+			let mut matched = false;
+			for token in tokens {
+				let global = self.treat_set_as_global && token == "global";
+				if global
+					|| tags
+						.iter()
+						.any(|tag| self.matcher.matches(token, tag.as_ref()))
+				{
+					state.record(pattern, token);
+					matched = true;
 				}
 			}
+			if matched {
+				state.file_tags.insert(pattern.clone(), tokens.clone());
+			}
 		}
-		outcome.reset();
+		state
 	}
 
-	// Also check submodules discovered from .git/modules
-	for submodule_path in discover_submodules(repo, worktree)? {
-		if processed_submodules.contains(&submodule_path) {
-			continue;
+	/// Tags whose whole selection (including `global` files) is already checked out by the
+	/// sparse-checkout `patterns`, e.g. the contents of an existing `info/sparse-checkout`.
+	/// `global` itself is never reported.
+	pub fn tags_covered_by(&self, patterns: &[String]) -> Vec<String> {
+		let sparse = SparsePatterns::new(patterns);
+		self.tag_counts()
+			.into_inner()
+			.into_keys()
+			.filter(|tag| tag != "global")
+			.filter(|tag| {
+				self.collect_matching(tag)
+					.patterns
+					.iter()
+					.all(|pattern| sparse.includes(pattern))
+			})
+			.collect()
+	}
+
+	/// Drop every file that `filter` rejects.
+	pub fn retain(&mut self, filter: &PathFilter) {
+		self.files.retain(|pattern, _| filter.allows(pattern));
+	}
+
+	/// Replace the files of every directory whose index entries are all in `patterns` with a
+	/// single `/dir/` pattern, using the outermost such directory.
+	///
+	/// The pattern is anchored because a bare `dir/` would also select every nested directory
+	/// of the same name. Directories holding a gitlink or a file the path filter dropped are
+	/// never collapsed, since a `/dir/` pattern would select those too.
+	pub fn compact(&self, patterns: &BTreeSet<String>) -> BTreeSet<String> {
+		self.compact_counts(patterns).into_keys().collect()
+	}
+
+	/// [`compact`](Self::compact) `patterns`, keeping how many of them each emitted pattern
+	/// covers: one for a file, the number of matched files under it for a `/dir/` pattern.
+	pub fn compact_counts(&self, patterns: &BTreeSet<String>) -> BTreeMap<String, usize> {
+		let mut selected: BTreeMap<&str, usize> = BTreeMap::new();
+		for pattern in patterns {
+			for dir in parent_dirs(pattern) {
+				*selected.entry(dir).or_insert(0) += 1;
+			}
 		}
 
-		let submodule_worktree_path = worktree.base().join(&submodule_path);
-		if !submodule_worktree_path.exists() {
-			continue;
+		let mut counts = BTreeMap::new();
+		for pattern in patterns {
+			let emitted = parent_dirs(pattern)
+				.find(|dir| self.dir_entries.get(*dir) == selected.get(dir))
+				.map_or_else(|| pattern.clone(), |dir| format!("/{dir}/"));
+			*counts.entry(emitted).or_insert(0) += 1;
 		}
+		counts
+	}
+}
 
-		let (sub_repo, _) =
-			git::open_repository(Some(&submodule_worktree_path)).with_context(|| {
-				format!(
-					"failed to open submodule at {}",
-					submodule_worktree_path.display()
-				)
-			})?;
-		let sub_worktree = git::require_worktree(&sub_repo).with_context(|| {
-			format!(
-				"submodule at {} is bare; a worktree is required for this operation",
-				submodule_worktree_path.display()
-			)
-		})?;
+/// The directories containing `path`, outermost first.
+fn parent_dirs(path: &str) -> impl Iterator<Item = &str> {
+	path.match_indices('/').map(|(end, _)| &path[..end])
+}
 
-		let next_prefix = if prefix.is_empty() {
-			submodule_path.clone()
-		} else {
-			format!("{}/{}", prefix, submodule_path)
-		};
+/// Include and exclude globs applied to scanned file paths.
+#[derive(Debug, Default)]
+pub struct PathFilter {
+	include: Option<GlobSet>,
+	exclude: Option<GlobSet>,
+}
 
-		discover_tags_recursive(
-			&sub_repo,
-			&sub_worktree,
-			&next_prefix,
-			tag_counts,
-			attribute,
-		)?;
-		processed_submodules.insert(submodule_path);
+impl PathFilter {
+	/// Build a filter; an empty `include` list admits every path.
+	pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+		Ok(Self {
+			include: build_glob_set(include)?,
+			exclude: build_glob_set(exclude)?,
+		})
 	}
 
-	Ok(())
+	/// Whether `path` matches an include glob (or none were given) and no exclude glob.
+	pub fn allows(&self, path: &str) -> bool {
+		self.include.as_ref().is_none_or(|set| set.is_match(path))
+			&& !self.exclude.as_ref().is_some_and(|set| set.is_match(path))
+	}
+}
+
+fn build_glob_set(globs: &[String]) -> Result<Option<GlobSet>> {
+	if globs.is_empty() {
+		return Ok(None);
+	}
+	let mut builder = GlobSetBuilder::new();
+	for glob in globs {
+		builder.add(Glob::new(glob).with_context(|| format!("invalid glob '{}'", glob))?);
+	}
+	Ok(Some(builder.build()?))
+}
+
+/// Scan a repository and its submodules for every value of `attribute`.
+pub fn scan_attributes(
+	repo: &gix::Repository,
+	worktree: &gix::Worktree<'_>,
+	attribute: &str,
+) -> Result<AttributeScan> {
+	scan_attributes_with(repo, worktree, attribute, &PatternOptions::default())
+}
+
+/// Scan like [`scan_attributes`], applying `options` to the result.
+pub fn scan_attributes_with(
+	repo: &gix::Repository,
+	worktree: &gix::Worktree<'_>,
+	attribute: &str,
+	options: &PatternOptions,
+) -> Result<AttributeScan> {
+	let mut scan = AttributeScan::with_options(options);
+	scan_recursive(repo, worktree, "", &mut scan, attribute)?;
+	scan.retain(&options.filter);
+	Ok(scan)
+}
+
+/// Options shaping which scanned files become sparse patterns.
+#[derive(Debug)]
+pub struct PatternOptions {
+	/// Include/exclude globs applied to file paths.
+	pub filter: PathFilter,
+	/// Treat a set-without-value attribute as the `global` tag that matches every tag
+	/// (default). When false, such files are skipped and `global` is an ordinary tag.
+	pub treat_set_as_global: bool,
+	/// Compares tokens with the requested tag; substring matching by default.
+	pub matcher: TagMatcher,
+	/// Characters that separate tags in an attribute value; [`TAG_SEPARATORS`] by default.
+	pub separators: Vec<char>,
+}
+
+impl Default for PatternOptions {
+	fn default() -> Self {
+		Self {
+			filter: PathFilter::default(),
+			treat_set_as_global: true,
+			matcher: TagMatcher::default(),
+			separators: TAG_SEPARATORS.to_vec(),
+		}
+	}
+}
+
+/// Sorted sparse-checkout patterns for `tag` in the repository at `repo_dir`.
+///
+/// This is the headless counterpart of `generate-sparse-list`: no output, no prompts.
+pub fn sparse_patterns_for(
+	repo_dir: &Path,
+	tag: &str,
+	attribute: &str,
+	options: &PatternOptions,
+) -> Result<Vec<String>> {
+	let (repo, _) = git::open_repository(Some(repo_dir))?;
+	let scan = scan_repository_with(&repo, attribute, options)?;
+	Ok(scan.collect_matching(tag).patterns.into_iter().collect())
+}
+
+/// Scan `repo` from its worktree, or from its HEAD tree when it is bare (see
+/// [`scan_head_tree_with`]).
+pub fn scan_repository_with(
+	repo: &gix::Repository,
+	attribute: &str,
+	options: &PatternOptions,
+) -> Result<AttributeScan> {
+	match repo.worktree() {
+		Some(worktree) => scan_attributes_with(repo, &worktree, attribute, options),
+		None => scan_head_tree_with(repo, attribute, options).context(
+			"repository is bare and its attributes could not be read from HEAD; a worktree is \
+			 required for this operation",
+		),
+	}
+}
+
+/// Discover all unique tags/attributes in a repository and its submodules.
+///
+/// This traverses the entire repository (and recursively into submodules)
+/// to find all values of the specified attribute.
+pub fn discover_all_tags(
+	repo: &gix::Repository,
+	worktree: &gix::Worktree<'_>,
+	attribute: &str,
+) -> Result<TagCounts> {
+	Ok(scan_attributes(repo, worktree, attribute)?.tag_counts())
 }
 
 /// Collect files matching a specific tag from a repository and its submodules.
@@ -237,82 +459,151 @@ pub fn collect_matching_files(
 	tag: &str,
 	attribute: &str,
 ) -> Result<CollectState> {
-	let mut state = CollectState::new();
-	collect_files_recursive(repo, worktree, tag, "", &mut state, attribute)?;
-	Ok(state)
+	Ok(scan_attributes(repo, worktree, attribute)?.collect_matching(tag))
 }
 
-fn collect_files_recursive<'repo>(
-	repo: &'repo gix::Repository,
-	worktree: &gix::Worktree<'repo>,
-	tag: &str,
+fn scan_recursive(
+	repo: &gix::Repository,
+	worktree: &gix::Worktree<'_>,
 	prefix: &str,
-	state: &mut CollectState,
+	scan: &mut AttributeScan,
 	attribute: &str,
 ) -> Result<()> {
 	let base_display = worktree.base().display().to_string();
-	let mut attr_stack = worktree
-		.attributes(None)
-		.with_context(|| format!("failed to load git attribute stack for {}", base_display))?;
-	let mut outcome = attr_stack.selected_attribute_matches([attribute]);
-
-	let index = repo.open_index().with_context(|| {
+	// A freshly initialised repository has no index file yet; treat it as empty.
+	let index = repo.index_or_empty().with_context(|| {
 		format!(
 			"failed to load git index for repository at {}",
 			base_display
 		)
 	})?;
+	let mut attr_stack = repo
+		.attributes_only(
+			&index,
+			gix::worktree::stack::state::attributes::Source::WorktreeThenIdMapping,
+		)
+		.with_context(|| format!("failed to load git attribute stack for {}", base_display))?;
 
+	let gitlinks = scan_entries(&index, &mut attr_stack, prefix, scan, attribute)?;
 	let mut processed_submodules: BTreeSet<String> = BTreeSet::new();
+	for local_path in gitlinks {
+		scan_submodule(worktree, prefix, &local_path, scan, attribute)?;
+		processed_submodules.insert(local_path);
+	}
+
+	// Also check submodules discovered from .git/modules
+	for submodule_path in discover_submodules(repo, worktree)? {
+		if processed_submodules.contains(&submodule_path) {
+			continue;
+		}
+		scan_submodule(worktree, prefix, &submodule_path, scan, attribute)?;
+		processed_submodules.insert(submodule_path);
+	}
+
+	Ok(())
+}
+
+/// Scan the `HEAD` tree of a repository that has no worktree, such as a bare mirror.
+///
+/// `.gitattributes` files are read from their blobs rather than from disk. Submodules can't
+/// be entered without a checkout, so gitlinks are skipped. A repository without commits
+/// yields an empty scan.
+pub fn scan_head_tree_with(
+	repo: &gix::Repository,
+	attribute: &str,
+	options: &PatternOptions,
+) -> Result<AttributeScan> {
+	let git_dir = repo.git_dir().display().to_string();
+	if repo
+		.head()
+		.with_context(|| format!("failed to read HEAD of {}", git_dir))?
+		.is_unborn()
+	{
+		return Ok(AttributeScan::with_options(options));
+	}
+
+	let tree_id = repo
+		.head_tree_id()
+		.with_context(|| format!("failed to resolve the HEAD tree of {}", git_dir))?;
+	scan_tree_with(repo, tree_id.detach(), attribute, options)
+}
+
+/// Scan the tree of `commit`, whatever the index and worktree currently hold.
+///
+/// Like [`scan_head_tree_with`], gitlinks are skipped.
+pub fn scan_commit_with(
+	repo: &gix::Repository,
+	commit: &str,
+	attribute: &str,
+	options: &PatternOptions,
+) -> Result<AttributeScan> {
+	let git_dir = repo.git_dir().display().to_string();
+	let resolve = || -> Result<gix::Id<'_>> {
+		Ok(repo
+			.rev_parse_single(commit)?
+			.object()?
+			.peel_to_commit()?
+			.tree_id()?)
+	};
+	let tree_id = resolve()
+		.with_context(|| format!("failed to resolve the tree of {} in {}", commit, git_dir))?;
+	scan_tree_with(repo, tree_id.detach(), attribute, options)
+}
+
+fn scan_tree_with(
+	repo: &gix::Repository,
+	tree_id: gix::ObjectId,
+	attribute: &str,
+	options: &PatternOptions,
+) -> Result<AttributeScan> {
+	let mut scan = AttributeScan::with_options(options);
+	let git_dir = repo.git_dir().display().to_string();
+	let index = repo
+		.index_from_tree(&tree_id)
+		.with_context(|| format!("failed to read tree {} of {}", tree_id, git_dir))?;
+	let mut attr_stack = repo
+		.attributes_only(
+			&index,
+			gix::worktree::stack::state::attributes::Source::IdMapping,
+		)
+		.with_context(|| format!("failed to load git attribute stack for {}", git_dir))?;
+
+	scan_entries(&index, &mut attr_stack, "", &mut scan, attribute)?;
+	scan.retain(&options.filter);
+	Ok(scan)
+}
+
+/// Record the tags of every file in `index`, returning the paths of gitlinks for the caller
+/// to recurse into.
+fn scan_entries(
+	index: &gix::index::State,
+	attr_stack: &mut gix::AttributeStack<'_>,
+	prefix: &str,
+	scan: &mut AttributeScan,
+	attribute: &str,
+) -> Result<Vec<String>> {
+	let mut outcome = attr_stack.selected_attribute_matches([attribute]);
+	let mut gitlinks = Vec::new();
 
 	for entry in index.entries() {
-		let path = entry.path(&index);
+		let path = entry.path(index);
 		let path_display = path.to_str_lossy();
 		let local_path = path_display.as_ref();
 
-		if entry.mode == gix::index::entry::Mode::COMMIT {
-			processed_submodules.insert(local_path.to_owned());
-			let submodule_worktree_path = worktree.base().join(local_path);
-			if !submodule_worktree_path.exists() {
-				continue;
-			}
-
-			let (sub_repo, _) =
-				git::open_repository(Some(&submodule_worktree_path)).with_context(|| {
-					format!(
-						"failed to open submodule at {}",
-						submodule_worktree_path.display()
-					)
-				})?;
-			let sub_worktree = git::require_worktree(&sub_repo).with_context(|| {
-				format!(
-					"submodule at {} is bare; a worktree is required for this operation",
-					submodule_worktree_path.display()
-				)
-			})?;
-
-			let next_prefix = if prefix.is_empty() {
-				local_path.to_owned()
-			} else {
-				format!("{}/{}", prefix, local_path)
-			};
+		let pattern = join_prefix(prefix, local_path);
+		for dir in parent_dirs(&pattern) {
+			*scan.dir_entries.entry(dir.to_owned()).or_insert(0) += 1;
+		}
 
-			collect_files_recursive(
-				&sub_repo,
-				&sub_worktree,
-				tag,
-				&next_prefix,
-				state,
-				attribute,
-			)?;
+		if entry.mode == gix::index::entry::Mode::COMMIT {
+			gitlinks.push(local_path.to_owned());
 			continue;
 		}
 
-		let pattern = if prefix.is_empty() {
-			local_path.to_owned()
-		} else {
-			format!("{}/{}", prefix, local_path)
-		};
+		scan.indexed_files += 1;
+		if local_path.rsplit('/').next() == Some(".gitattributes") {
+			scan.attribute_files += 1;
+		}
 
 		let platform = attr_stack
 			.at_entry(path, Some(entry.mode))
@@ -321,128 +612,512 @@ fn collect_files_recursive<'repo>(
 		if platform.matching_attributes(&mut outcome)
 			&& let Some(attr_state) = outcome.iter_selected().next().map(|m| m.assignment.state)
 		{
-			match attr_state {
-				StateRef::Unspecified | StateRef::Unset => {}
-				StateRef::Set => {
-					state.record_match(&pattern, "global", tag);
-				}
+			let tokens: Vec<String> = match attr_state {
+				StateRef::Unspecified | StateRef::Unset => Vec::new(),
+				StateRef::Set if scan.treat_set_as_global => vec!["global".to_owned()],
+				StateRef::Set => Vec::new(),
 				StateRef::Value(value) => {
-					let raw = value.as_bstr().to_str_lossy();
-					for token in raw
-						.split(',')
-						.map(|token| token.trim())
-						.filter(|s| !s.is_empty())
-					{
-						state.record_match(&pattern, token, tag);
-					}
+					split_tags_with(&value.as_bstr().to_str_lossy(), &scan.separators)
+						.map(str::to_owned)
+						.collect()
 				}
+			};
+			if !tokens.is_empty() {
+				scan.files.insert(pattern, tokens);
 			}
 		}
 		outcome.reset();
 	}
 
-	// Also check submodules discovered from .git/modules
-	for submodule_path in discover_submodules(repo, worktree)? {
-		if processed_submodules.contains(&submodule_path) {
-			continue;
-		}
+	Ok(gitlinks)
+}
 
-		let submodule_worktree_path = worktree.base().join(&submodule_path);
-		if !submodule_worktree_path.exists() {
-			continue;
-		}
+/// Recurse into the submodule at `local_path` if it is checked out.
+fn scan_submodule(
+	worktree: &gix::Worktree<'_>,
+	prefix: &str,
+	local_path: &str,
+	scan: &mut AttributeScan,
+	attribute: &str,
+) -> Result<()> {
+	let submodule_worktree_path = worktree.base().join(local_path);
+	if !submodule_worktree_path.exists() {
+		return Ok(());
+	}
 
-		let (sub_repo, _) =
-			git::open_repository(Some(&submodule_worktree_path)).with_context(|| {
-				format!(
-					"failed to open submodule at {}",
-					submodule_worktree_path.display()
-				)
-			})?;
-		let sub_worktree = git::require_worktree(&sub_repo).with_context(|| {
+	let (sub_repo, _) =
+		git::open_repository(Some(&submodule_worktree_path)).with_context(|| {
 			format!(
-				"submodule at {} is bare; a worktree is required for this operation",
+				"failed to open submodule at {}",
 				submodule_worktree_path.display()
 			)
 		})?;
+	let sub_worktree = git::require_worktree(&sub_repo).with_context(|| {
+		format!(
+			"submodule at {} is bare; a worktree is required for this operation",
+			submodule_worktree_path.display()
+		)
+	})?;
 
-		let next_prefix = if prefix.is_empty() {
-			submodule_path.clone()
-		} else {
-			format!("{}/{}", prefix, submodule_path)
-		};
+	scan_recursive(
+		&sub_repo,
+		&sub_worktree,
+		&join_prefix(prefix, local_path),
+		scan,
+		attribute,
+	)
+}
 
-		collect_files_recursive(
-			&sub_repo,
-			&sub_worktree,
-			tag,
-			&next_prefix,
-			state,
-			attribute,
-		)?;
-		processed_submodules.insert(submodule_path);
-	}
+fn join_prefix(prefix: &str, path: &str) -> String {
+	let joined = if prefix.is_empty() {
+		path.to_owned()
+	} else {
+		format!("{}/{}", prefix, path)
+	};
+	normalize_pattern(&joined)
+}
 
-	Ok(())
+/// Use forward slashes in a sparse pattern, as git's sparse-checkout expects.
+///
+/// Index paths are already `/`-separated, but submodule prefixes come from the filesystem.
+/// A backslash is a legal file name character elsewhere, so only Windows is rewritten.
+fn normalize_pattern(path: &str) -> String {
+	if cfg!(windows) {
+		path.replace('\\', "/")
+	} else {
+		path.to_owned()
+	}
 }
 
-/// Scan a repository for patterns matching a tag (used by setup command).
+/// Collect the sparse-checkout patterns matching a tag (used by setup command).
 ///
-/// This is a simplified version that only collects matching file patterns,
-/// used when generating sparse checkout patterns.
+/// This shares the scan used by `generate-sparse-list`, so setup sees the same files,
+/// including those in checked-out submodules.
 pub fn collect_sparse_patterns(
 	repo: &gix::Repository,
 	worktree: &gix::Worktree<'_>,
 	tag: &str,
 	attribute: &str,
 ) -> Result<BTreeSet<String>> {
-	let mut patterns = BTreeSet::new();
-	collect_patterns_recursive(repo, worktree, tag, attribute, &mut patterns)?;
-	Ok(patterns)
+	Ok(scan_attributes(repo, worktree, attribute)?
+		.collect_matching(tag)
+		.patterns)
 }
 
-fn collect_patterns_recursive(
-	repo: &gix::Repository,
-	worktree: &gix::Worktree<'_>,
-	tag: &str,
-	attribute: &str,
-	patterns: &mut BTreeSet<String>,
-) -> Result<()> {
-	let mut attr_stack = worktree
-		.attributes(None)
-		.context("failed to load git attribute stack")?;
-	let mut outcome = attr_stack.selected_attribute_matches([attribute]);
+#[cfg(test)]
+mod tests {
+	use std::fs;
 
-	let index = repo.open_index().context("failed to load git index")?;
+	use super::*;
+	use crate::git::{git, repository};
 
-	for entry in index.entries() {
-		let path = entry.path(&index);
-		let platform = attr_stack
-			.at_entry(path, Some(entry.mode))
-			.with_context(|| format!("failed to evaluate attributes for {}", path))?;
+	/// Create a repository with the given files staged in its index.
+	fn repo_with_files(dir: &Path, files: &[(&str, &str)]) {
+		repository::init(dir).unwrap();
+		for (path, contents) in files {
+			let full = dir.join(path);
+			fs::create_dir_all(full.parent().unwrap()).unwrap();
+			fs::write(full, contents).unwrap();
+		}
+		git().cwd(dir).args(["add", "-A"]).run().unwrap();
+	}
 
-		if platform.matching_attributes(&mut outcome)
-			&& let Some(state) = outcome.iter_selected().next().map(|m| m.assignment.state)
-		{
-			match state {
-				StateRef::Set => {
-					// Global tag
-					patterns.insert(path.to_str_lossy().into_owned());
-				}
-				StateRef::Value(value) => {
-					let raw = value.as_bstr().to_str_lossy();
-					for token in raw.split(',').map(|s| s.trim()) {
-						if token == "global" || token.contains(tag) {
-							patterns.insert(path.to_str_lossy().into_owned());
-							break;
-						}
-					}
-				}
-				_ => {}
-			}
+	fn tagged_repo(dir: &Path) {
+		repo_with_files(
+			dir,
+			&[
+				(
+					".gitattributes",
+					"a/** projects=alpha\nb/** projects=beta,alpha-extra\ncommon/** projects\n",
+				),
+				("a/one.txt", ""),
+				("b/two.txt", ""),
+				("common/shared.txt", ""),
+				("untagged.txt", ""),
+			],
+		);
+	}
+
+	#[test]
+	fn test_patterns_use_forward_slashes() {
+		let dir = tempfile::tempdir().unwrap();
+		repo_with_files(
+			dir.path(),
+			&[
+				(".gitattributes", "deep/** projects=alpha\n"),
+				("deep/nested/dir/file.txt", ""),
+			],
+		);
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let patterns = collect_sparse_patterns(&repo, &worktree, "alpha", "projects").unwrap();
+		assert_eq!(
+			patterns.into_iter().collect::<Vec<_>>(),
+			["deep/nested/dir/file.txt"]
+		);
+		assert!(!join_prefix("sub", "a/b.txt").contains('\\'));
+		if cfg!(windows) {
+			assert_eq!(join_prefix("sub\\mod", "a/b.txt"), "sub/mod/a/b.txt");
 		}
-		outcome.reset();
 	}
 
-	Ok(())
+	#[test]
+	fn test_path_filter_include_only() {
+		let dir = tempfile::tempdir().unwrap();
+		tagged_repo(dir.path());
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let mut scan = scan_attributes(&repo, &worktree, "projects").unwrap();
+		scan.retain(&PathFilter::new(&["a/**".to_owned(), "b/**".to_owned()], &[]).unwrap());
+		assert_eq!(
+			scan.collect_matching("alpha")
+				.patterns
+				.into_iter()
+				.collect::<Vec<_>>(),
+			["a/one.txt", "b/two.txt"]
+		);
+	}
+
+	#[test]
+	fn test_path_filter_exclude_only() {
+		let dir = tempfile::tempdir().unwrap();
+		tagged_repo(dir.path());
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let mut scan = scan_attributes(&repo, &worktree, "projects").unwrap();
+		scan.retain(&PathFilter::new(&[], &["common/**".to_owned()]).unwrap());
+		assert!(!scan.tag_counts().into_inner().contains_key("global"));
+		assert_eq!(
+			scan.collect_matching("alpha")
+				.patterns
+				.into_iter()
+				.collect::<Vec<_>>(),
+			["a/one.txt", "b/two.txt"]
+		);
+		assert!(PathFilter::new(&[], &["[".to_owned()]).is_err());
+	}
+
+	#[test]
+	fn test_compact_collapses_fully_selected_directories() {
+		let dir = tempfile::tempdir().unwrap();
+		repo_with_files(
+			dir.path(),
+			&[
+				(
+					".gitattributes",
+					"a/** projects=alpha\nb/x.txt projects=alpha\n",
+				),
+				("a/one.txt", ""),
+				("a/deep/two.txt", ""),
+				("b/x.txt", ""),
+				("b/y.txt", ""),
+				("b/a/z.txt", ""),
+			],
+		);
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let mut scan = scan_attributes(&repo, &worktree, "projects").unwrap();
+		let patterns = scan.collect_matching("alpha").patterns;
+		let compacted = scan.compact(&patterns);
+		assert_eq!(compacted.iter().collect::<Vec<_>>(), ["/a/", "b/x.txt"]);
+
+		// The untagged `b/a/` must stay outside the sparse checkout.
+		let matches_dir = |pattern: &str, dir: &str| {
+			gix::glob::parse(pattern)
+				.unwrap()
+				.matches_repo_relative_path(
+					dir.into(),
+					dir.rfind('/').map(|slash| slash + 1),
+					Some(true),
+					gix::glob::pattern::Case::Sensitive,
+					gix::glob::wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+				)
+		};
+		assert!(compacted.iter().any(|pattern| matches_dir(pattern, "a")));
+		assert!(!compacted.iter().any(|pattern| matches_dir(pattern, "b/a")));
+		assert!(matches_dir("a/", "b/a"));
+		assert_eq!(
+			scan.compact_counts(&patterns)
+				.into_iter()
+				.collect::<Vec<_>>(),
+			[("/a/".to_owned(), 2), ("b/x.txt".to_owned(), 1)]
+		);
+
+		// A filtered-out file keeps its directory from collapsing, but not its sibling.
+		scan.retain(&PathFilter::new(&[], &["a/one.txt".to_owned()]).unwrap());
+		let patterns = scan.collect_matching("alpha").patterns;
+		assert_eq!(
+			scan.compact(&patterns).into_iter().collect::<Vec<_>>(),
+			["/a/deep/", "b/x.txt"]
+		);
+	}
+
+	#[test]
+	fn test_sparse_patterns_for_is_sorted_and_filtered() {
+		let dir = tempfile::tempdir().unwrap();
+		tagged_repo(dir.path());
+
+		let options = PatternOptions::default();
+		assert_eq!(
+			sparse_patterns_for(dir.path(), "alpha", "projects", &options).unwrap(),
+			["a/one.txt", "b/two.txt", "common/shared.txt"]
+		);
+
+		let options = PatternOptions {
+			filter: PathFilter::new(&[], &["b/**".to_owned()]).unwrap(),
+			..PatternOptions::default()
+		};
+		assert_eq!(
+			sparse_patterns_for(dir.path(), "alpha", "projects", &options).unwrap(),
+			["a/one.txt", "common/shared.txt"]
+		);
+	}
+
+	#[test]
+	fn test_treat_set_as_global_modes() {
+		let dir = tempfile::tempdir().unwrap();
+		repo_with_files(
+			dir.path(),
+			&[
+				(
+					".gitattributes",
+					"a/** projects=alpha\nshared/** projects\nworld/** projects=global\n",
+				),
+				("a/one.txt", ""),
+				("shared/common.txt", ""),
+				("world/map.txt", ""),
+			],
+		);
+
+		let patterns = |treat_set_as_global, tag| {
+			let options = PatternOptions {
+				treat_set_as_global,
+				..PatternOptions::default()
+			};
+			sparse_patterns_for(dir.path(), tag, "projects", &options).unwrap()
+		};
+
+		// Default: bare `projects` and `projects=global` both match every tag.
+		assert_eq!(
+			patterns(true, "alpha"),
+			["a/one.txt", "shared/common.txt", "world/map.txt"]
+		);
+		// Without it, bare attributes are ignored and `global` is just another tag.
+		assert_eq!(patterns(false, "alpha"), ["a/one.txt"]);
+		assert_eq!(patterns(false, "global"), ["world/map.txt"]);
+	}
+
+	#[test]
+	fn test_tag_matchers() {
+		let dir = tempfile::tempdir().unwrap();
+		tagged_repo(dir.path());
+
+		let patterns = |matcher, tag| {
+			let options = PatternOptions {
+				matcher,
+				..PatternOptions::default()
+			};
+			sparse_patterns_for(dir.path(), tag, "projects", &options).unwrap()
+		};
+
+		assert_eq!(
+			patterns(TagMatcher::substring(), "alpha"),
+			["a/one.txt", "b/two.txt", "common/shared.txt"]
+		);
+		assert_eq!(
+			patterns(TagMatcher::exact(), "alpha"),
+			["a/one.txt", "common/shared.txt"]
+		);
+		assert_eq!(
+			patterns(TagMatcher::glob(), "*-extra"),
+			["b/two.txt", "common/shared.txt"]
+		);
+		assert_eq!(
+			patterns(TagMatcher::new(|token, _| token == "beta"), "ignored"),
+			["b/two.txt", "common/shared.txt"]
+		);
+	}
+
+	#[test]
+	fn test_split_tags_accepts_commas_and_semicolons() {
+		assert_eq!(
+			split_tags(" alpha;beta ,, gamma;").collect::<Vec<_>>(),
+			["alpha", "beta", "gamma"]
+		);
+		assert_eq!(
+			split_tags_with("alpha|beta,gamma", &['|']).collect::<Vec<_>>(),
+			["alpha", "beta,gamma"]
+		);
+	}
+
+	#[test]
+	fn test_semicolon_tags_match_in_scan_and_setup_patterns() {
+		let dir = tempfile::tempdir().unwrap();
+		repo_with_files(
+			dir.path(),
+			&[
+				(".gitattributes", "a/** projects=beta;alpha\n"),
+				("a/one.txt", ""),
+			],
+		);
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let scan = scan_attributes(&repo, &worktree, "projects").unwrap();
+		assert_eq!(scan.files["a/one.txt"], ["beta", "alpha"]);
+		assert_eq!(
+			collect_sparse_patterns(&repo, &worktree, "alpha", "projects").unwrap(),
+			BTreeSet::from(["a/one.txt".to_owned()])
+		);
+	}
+
+	#[test]
+	fn test_scan_serves_tag_counts_and_matches() {
+		let dir = tempfile::tempdir().unwrap();
+		tagged_repo(dir.path());
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let scan = scan_attributes(&repo, &worktree, "projects").unwrap();
+		assert_eq!(scan.attribute_files, 1);
+		assert_eq!(
+			scan.tag_counts().into_inner(),
+			BTreeMap::from([
+				("alpha".to_owned(), 1),
+				("alpha-extra".to_owned(), 1),
+				("beta".to_owned(), 1),
+				("global".to_owned(), 1),
+			])
+		);
+
+		let state = scan.collect_matching("alpha");
+		assert_eq!(
+			state.patterns.into_iter().collect::<Vec<_>>(),
+			["a/one.txt", "b/two.txt", "common/shared.txt"]
+		);
+		// Only the global file matches an unknown tag.
+		assert_eq!(scan.collect_matching("gamma").patterns.len(), 1);
+
+		// `alpha` also needs `a/one.txt`; the leading `/` of sparse patterns is ignored.
+		let current = ["/b/two.txt", "/common/shared.txt"].map(String::from);
+		assert_eq!(scan.tags_covered_by(&current), ["alpha-extra", "beta"]);
+		// Compacted directories cover their files, and negations uncover them again.
+		let current = ["/b/", "/common/"].map(String::from);
+		assert_eq!(scan.tags_covered_by(&current), ["alpha-extra", "beta"]);
+		let current = ["/b/", "/common/", "!/b/two.txt", "*.txt"].map(String::from);
+		assert_eq!(
+			scan.tags_covered_by(&current),
+			["alpha", "alpha-extra", "beta"]
+		);
+		let current = ["*.txt", "!/b/two.txt"].map(String::from);
+		assert!(scan.tags_covered_by(&current).is_empty());
+
+		// Several tags union their files and count each token once.
+		let state = scan.collect_matching_any(&["beta", "alpha"]);
+		assert_eq!(state.patterns.len(), 3);
+		assert_eq!(state.tag_counts["alpha-extra"], 1);
+		assert_eq!(state.tag_counts["global"], 1);
+
+		// Matched tags are a subset; the full list keeps the attribute's order.
+		let state = scan.collect_matching("beta");
+		assert_eq!(
+			state.file_map["b/two.txt"].iter().collect::<Vec<_>>(),
+			["beta"]
+		);
+		assert_eq!(state.file_tags["b/two.txt"], ["beta", "alpha-extra"]);
+		assert!(!state.file_tags.contains_key("a/one.txt"));
+		assert_eq!(scan.indexed_files, 5);
+	}
+
+	#[test]
+	fn test_scan_of_empty_repository_counts_no_files() {
+		let dir = tempfile::tempdir().unwrap();
+		git::repository::init(dir.path()).unwrap();
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let scan = scan_attributes(&repo, &worktree, "projects").unwrap();
+		assert_eq!(scan.indexed_files, 0);
+		assert_eq!(scan.attribute_files, 0);
+	}
+
+	#[test]
+	fn test_generate_entry_points_pin_pattern_output() {
+		let dir = tempfile::tempdir().unwrap();
+		tagged_repo(dir.path());
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+
+		let state = collect_matching_files(&repo, &worktree, "beta", "projects").unwrap();
+		assert_eq!(
+			state
+				.patterns
+				.iter()
+				.map(String::as_str)
+				.collect::<Vec<_>>(),
+			["b/two.txt", "common/shared.txt"]
+		);
+		assert_eq!(
+			state.matches,
+			[
+				("b/two.txt".to_owned(), "beta".to_owned()),
+				("common/shared.txt".to_owned(), "global".to_owned()),
+			]
+		);
+
+		// Setup's entry point emits exactly the patterns generate would.
+		assert_eq!(
+			collect_sparse_patterns(&repo, &worktree, "beta", "projects").unwrap(),
+			state.patterns
+		);
+
+		let tag_counts = discover_all_tags(&repo, &worktree, "projects").unwrap();
+		assert_eq!(
+			tag_counts.into_inner().into_keys().collect::<Vec<_>>(),
+			["alpha", "alpha-extra", "beta", "global"]
+		);
+	}
+
+	#[test]
+	fn test_scan_head_tree_of_bare_mirror() {
+		let dir = tempfile::tempdir().unwrap();
+		let source = dir.path().join("source");
+		tagged_repo(&source);
+		git()
+			.cwd(&source)
+			.args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+			.args(["commit", "-q", "-m", "init"])
+			.run()
+			.unwrap();
+		let mirror = dir.path().join("mirror.git");
+		git()
+			.args(["clone", "-q", "--bare"])
+			.arg(&source)
+			.arg(&mirror)
+			.run()
+			.unwrap();
+
+		let (repo, _) = git::open_repository(Some(&mirror)).unwrap();
+		assert!(repo.worktree().is_none());
+		let scan = scan_head_tree_with(&repo, "projects", &PatternOptions::default()).unwrap();
+
+		assert_eq!(scan.attribute_files, 1);
+		assert_eq!(
+			scan.collect_matching("alpha")
+				.patterns
+				.into_iter()
+				.collect::<Vec<_>>(),
+			["a/one.txt", "b/two.txt", "common/shared.txt"]
+		);
+
+		// The worktree-independent entry points take the same path.
+		assert_eq!(
+			sparse_patterns_for(&mirror, "beta", "projects", &PatternOptions::default()).unwrap(),
+			["b/two.txt", "common/shared.txt"]
+		);
+	}
 }
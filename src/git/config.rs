@@ -3,7 +3,6 @@
 //! This module provides utilities for reading and writing git configuration files
 //! (`.gitmodules`, local config, etc.) using the gix library.
 
-use std::fs;
 use std::path::Path;
 
 use anyhow::{Context, Result};
@@ -11,11 +10,17 @@ use gix::bstr::{BStr, BString, ByteSlice};
 use gix::config::{File as GitConfigFile, Source};
 use gix::sec::Trust;
 
+use crate::atomic;
+
 /// A wrapper around a git configuration file for easier manipulation.
+///
+/// Edits are applied to the parsed file in place, so saving preserves comments, ordering,
+/// and unrelated sections verbatim. Saving replaces the file atomically.
 pub struct ConfigFile {
 	file: GitConfigFile<'static>,
 	path: std::path::PathBuf,
 	dirty: bool,
+	backup: bool,
 }
 
 impl ConfigFile {
@@ -35,6 +40,7 @@ impl ConfigFile {
 			file,
 			path: path.to_path_buf(),
 			dirty: false,
+			backup: false,
 		})
 	}
 
@@ -47,9 +53,16 @@ impl ConfigFile {
 			file,
 			path: path.to_path_buf(),
 			dirty: false,
+			backup: false,
 		})
 	}
 
+	/// Keep the previous contents as `<file>.bak` when saving.
+	pub fn with_backup(mut self, backup: bool) -> Self {
+		self.backup = backup;
+		self
+	}
+
 	/// Set a value in the configuration file.
 	///
 	/// Returns `true` if the value was changed (or newly set).
@@ -107,7 +120,11 @@ impl ConfigFile {
 
 		let mut buf = Vec::new();
 		self.file.write_to(&mut buf)?;
-		fs::write(&self.path, buf)?;
+		if self.backup {
+			atomic::write_with_backup(&self.path, buf)?;
+		} else {
+			atomic::write(&self.path, buf)?;
+		}
 		Ok(true)
 	}
 
@@ -120,11 +137,21 @@ impl ConfigFile {
 /// Helper for managing submodule configuration in `.gitmodules` and local config.
 pub struct SubmoduleConfig<'a> {
 	name: &'a str,
+	backup: bool,
 }
 
 impl<'a> SubmoduleConfig<'a> {
 	pub fn new(name: &'a str) -> Self {
-		Self { name }
+		Self {
+			name,
+			backup: false,
+		}
+	}
+
+	/// Keep a `.bak` copy of each file before it is rewritten.
+	pub fn with_backup(mut self, backup: bool) -> Self {
+		self.backup = backup;
+		self
 	}
 
 	/// Ensure submodule entry exists in `.gitmodules` with the given values.
@@ -137,7 +164,7 @@ impl<'a> SubmoduleConfig<'a> {
 		url: &str,
 		branch: &str,
 	) -> Result<bool> {
-		let mut config = ConfigFile::open_or_create(gitmodules_path)?;
+		let mut config = ConfigFile::open_or_create(gitmodules_path)?.with_backup(self.backup);
 
 		let mut changed = false;
 		changed |= config.set_value("submodule", Some(self.name), "path", path)?;
@@ -157,7 +184,7 @@ impl<'a> SubmoduleConfig<'a> {
 		url: &str,
 		branch: &str,
 	) -> Result<bool> {
-		let mut config = ConfigFile::open(git_config_path)?;
+		let mut config = ConfigFile::open(git_config_path)?.with_backup(self.backup);
 
 		let mut changed = false;
 		changed |= config.set_value("submodule", Some(self.name), "url", url)?;
@@ -175,7 +202,7 @@ impl<'a> SubmoduleConfig<'a> {
 			return Ok(false);
 		}
 
-		let mut config = ConfigFile::open(gitmodules_path)?;
+		let mut config = ConfigFile::open(gitmodules_path)?.with_backup(self.backup);
 		let removed = config.remove_section("submodule", Some(self.name));
 		config.save()?;
 		Ok(removed)
@@ -189,9 +216,73 @@ impl<'a> SubmoduleConfig<'a> {
 			return Ok(false);
 		}
 
-		let mut config = ConfigFile::open(git_config_path)?;
+		let mut config = ConfigFile::open(git_config_path)?.with_backup(self.backup);
 		let removed = config.remove_section("submodule", Some(self.name));
 		config.save()?;
 		Ok(removed)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+
+	#[test]
+	fn test_edit_preserves_comments_and_other_sections() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join(".gitmodules");
+		let other =
+			"[submodule \"other\"]\n\tpath = vendor/other\n\turl = https://example.com/other.git\n";
+		let original = format!(
+			"# Shared asset submodules\n[submodule \"assets\"]\n\tpath = assets\n\turl = https://example.com/assets.git\n\tbranch = main\n{other}"
+		);
+		fs::write(&path, &original).unwrap();
+
+		let changed = SubmoduleConfig::new("assets")
+			.ensure_gitmodules(&path, "assets", "https://example.com/assets-v2.git", "main")
+			.unwrap();
+		assert!(changed);
+
+		let written = fs::read_to_string(&path).unwrap();
+		assert!(written.starts_with("# Shared asset submodules\n"));
+		assert!(written.contains("https://example.com/assets-v2.git"));
+		assert!(written.ends_with(&other));
+		assert_eq!(
+			written,
+			original.replace(
+				"https://example.com/assets.git",
+				"https://example.com/assets-v2.git"
+			)
+		);
+	}
+
+	#[test]
+	fn test_backup_only_written_when_changed() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join(".gitmodules");
+		let submodule = SubmoduleConfig::new("assets").with_backup(true);
+
+		assert!(
+			submodule
+				.ensure_gitmodules(&path, "assets", "https://example.com/a.git", "main")
+				.unwrap()
+		);
+		assert!(!atomic::backup_path(&path).exists());
+		let first = fs::read_to_string(&path).unwrap();
+
+		assert!(
+			!submodule
+				.ensure_gitmodules(&path, "assets", "https://example.com/a.git", "main")
+				.unwrap()
+		);
+		assert!(!atomic::backup_path(&path).exists());
+
+		assert!(submodule.remove_from_gitmodules(&path).unwrap());
+		assert_eq!(
+			fs::read_to_string(atomic::backup_path(&path)).unwrap(),
+			first
+		);
+	}
+}
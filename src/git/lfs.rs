@@ -4,21 +4,45 @@ use std::fs;
 use std::path::Path;
 
 use anyhow::Result;
+use walkdir::WalkDir;
 
 use super::git;
-use crate::output;
 
-/// Check if a repository uses Git LFS by looking for filter=lfs in .gitattributes.
+/// Check if a repository uses Git LFS by looking for an active `filter=lfs` rule in any
+/// `.gitattributes` file under the worktree.
 pub fn is_enabled(worktree_path: &Path) -> bool {
-	let gitattributes = worktree_path.join(".gitattributes");
-	if let Ok(content) = fs::read_to_string(&gitattributes) {
-		return content.contains("filter=lfs");
-	}
-	false
+	WalkDir::new(worktree_path)
+		.into_iter()
+		.filter_entry(|entry| entry.file_name() != ".git")
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().is_file() && entry.file_name() == ".gitattributes")
+		.any(|entry| {
+			fs::read_to_string(entry.path())
+				.map(|content| attributes_enable_lfs(&content))
+				.unwrap_or(false)
+		})
+}
+
+/// Check whether any non-comment line of a `.gitattributes` file assigns `filter=lfs`.
+fn attributes_enable_lfs(content: &str) -> bool {
+	content
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.any(|line| {
+			line.split_whitespace()
+				.skip(1)
+				.any(|attr| attr == "filter=lfs")
+		})
+}
+
+/// Check whether the `git lfs` extension is installed and runnable.
+pub fn is_available() -> bool {
+	git().args(["lfs", "version"]).ok().unwrap_or(false)
 }
 
-/// Install LFS hooks in a repository.
-pub fn install(git_dir: &Path, worktree: &Path) -> Result<bool> {
+/// Install LFS hooks in a repository, pushing the reason onto `warnings` if that fails.
+pub fn install(git_dir: &Path, worktree: &Path, warnings: &mut Vec<String>) -> Result<bool> {
 	let output = git()
 		.git_dir(git_dir)
 		.work_tree(worktree)
@@ -26,9 +50,9 @@ pub fn install(git_dir: &Path, worktree: &Path) -> Result<bool> {
 		.output()?;
 
 	if !output.status.success() {
-		output::warn(&format!(
+		warnings.push(format!(
 			"git lfs install failed (LFS may not be installed): {}",
-			String::from_utf8_lossy(&output.stderr)
+			String::from_utf8_lossy(&output.stderr).trim()
 		));
 		return Ok(false);
 	}
@@ -36,8 +60,8 @@ pub fn install(git_dir: &Path, worktree: &Path) -> Result<bool> {
 	Ok(true)
 }
 
-/// Fetch LFS objects for the current checkout.
-pub fn fetch(git_dir: &Path, worktree: &Path) -> Result<()> {
+/// Fetch LFS objects for the current checkout. A failed fetch is pushed onto `warnings`.
+pub fn fetch(git_dir: &Path, worktree: &Path, warnings: &mut Vec<String>) -> Result<()> {
 	let output = git()
 		.git_dir(git_dir)
 		.work_tree(worktree)
@@ -45,9 +69,9 @@ pub fn fetch(git_dir: &Path, worktree: &Path) -> Result<()> {
 		.output()?;
 
 	if !output.status.success() {
-		output::warn(&format!(
+		warnings.push(format!(
 			"git lfs fetch warning: {}",
-			String::from_utf8_lossy(&output.stderr)
+			String::from_utf8_lossy(&output.stderr).trim()
 		));
 		// Don't fail - alternates may already provide the objects
 	}
@@ -65,17 +89,58 @@ pub fn checkout(git_dir: &Path, worktree: &Path) -> Result<()> {
 }
 
 /// Fetch and checkout LFS objects for a sparse checkout.
-pub fn fetch_and_checkout(git_dir: &Path, worktree: &Path) -> Result<()> {
-	output::note("Fetching LFS objects...");
+///
+/// Returns `false` if LFS handling was skipped (git-lfs missing or install failed); the
+/// reason is pushed onto `warnings`.
+pub fn fetch_and_checkout(
+	git_dir: &Path,
+	worktree: &Path,
+	warnings: &mut Vec<String>,
+) -> Result<bool> {
+	if !is_available() {
+		warnings.push("git-lfs not found; skipping LFS objects".to_owned());
+		return Ok(false);
+	}
 
 	// Install LFS hooks
-	if !install(git_dir, worktree)? {
-		return Ok(());
+	if !install(git_dir, worktree, warnings)? {
+		return Ok(false);
 	}
 
 	// Fetch objects
-	fetch(git_dir, worktree)?;
+	fetch(git_dir, worktree, warnings)?;
 
 	// Checkout files
-	checkout(git_dir, worktree)
+	checkout(git_dir, worktree)?;
+	Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_commented_lfs_rule_is_ignored() {
+		let dir = tempfile::tempdir().unwrap();
+		fs::write(
+			dir.path().join(".gitattributes"),
+			"# *.psd filter=lfs diff=lfs merge=lfs -text\n",
+		)
+		.unwrap();
+		assert!(!is_enabled(dir.path()));
+	}
+
+	#[test]
+	fn test_nested_lfs_rule_is_detected() {
+		let dir = tempfile::tempdir().unwrap();
+		fs::write(dir.path().join(".gitattributes"), "*.txt text\n").unwrap();
+		let nested = dir.path().join("assets/models");
+		fs::create_dir_all(&nested).unwrap();
+		fs::write(
+			nested.join(".gitattributes"),
+			"*.blend filter=lfs diff=lfs merge=lfs -text\n",
+		)
+		.unwrap();
+		assert!(is_enabled(dir.path()));
+	}
 }
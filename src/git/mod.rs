@@ -8,6 +8,7 @@ pub mod cmd;
 pub mod config;
 pub mod lfs;
 pub mod repository;
+pub mod size;
 pub mod sparse;
 pub mod submodule;
 
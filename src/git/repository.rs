@@ -12,10 +12,8 @@ pub fn open_repository(start: Option<&Path>) -> Result<(gix::Repository, PathBuf
 		.with_context(|| format!("failed to discover git repository at {}", start.display()))?
 		.to_thread_local();
 
-	let root = repo
-		.workdir()
-		.or_else(|| repo.git_dir().parent())
-		.ok_or_else(|| anyhow::anyhow!("repository has no worktree or parent git directory"))?;
+	// A bare repository is its own root.
+	let root = repo.workdir().unwrap_or_else(|| repo.git_dir());
 
 	let root = fs::canonicalize(root)
 		.with_context(|| format!("failed to canonicalize {}", root.display()))?;
@@ -30,12 +28,156 @@ pub fn require_worktree(repo: &gix::Repository) -> Result<gix::Worktree<'_>> {
 	})
 }
 
+/// Initialize a git repository with a working tree at the given path.
+pub fn init(path: &Path) -> Result<()> {
+	super::git().args(["init", "-q"]).arg(path).run()
+}
+
 /// Initialize a bare git repository at the given path.
 pub fn init_bare(path: &Path) -> Result<()> {
 	super::git().args(["init", "--bare", "-q"]).arg(path).run()
 }
 
-/// Check if a path is a git repository.
+/// Ask the repository at `url` for its default branch, without a local repository.
+pub fn remote_default_branch(url: &str) -> Result<String> {
+	let listing = super::git()
+		.args(["ls-remote", "--symref", url, "HEAD"])
+		.stdout()
+		.with_context(|| format!("failed to query the default branch of {}", url))?;
+
+	symref_head_branch(&listing).ok_or_else(|| {
+		anyhow::anyhow!(
+			"unable to determine the default branch of {}; set SUBMODULE_BRANCH explicitly",
+			url
+		)
+	})
+}
+
+/// The branch `HEAD` points to in `git ls-remote --symref <remote> HEAD` output.
+fn symref_head_branch(listing: &str) -> Option<String> {
+	listing
+		.lines()
+		.find_map(|line| {
+			line.strip_prefix("ref: refs/heads/")
+				.and_then(|rest| rest.split_whitespace().next())
+		})
+		.map(str::to_owned)
+}
+
+/// Ask the repository at `url` which commit `reference` points to, without a local repository.
+///
+/// A branch wins over a tag of the same name, and annotated tags resolve to their commit.
+pub fn remote_commit(url: &str, reference: &str) -> Result<String> {
+	let listing = super::git()
+		.args(["ls-remote", url, reference])
+		.stdout()
+		.with_context(|| format!("failed to query {} for {}", url, reference))?;
+
+	ls_remote_commit(&listing, reference)
+		.ok_or_else(|| anyhow::anyhow!("{} has no branch or tag named {}", url, reference))
+}
+
+/// The commit `reference` names in `git ls-remote <remote> <reference>` output.
+fn ls_remote_commit(listing: &str, reference: &str) -> Option<String> {
+	let refs: Vec<(&str, &str)> = listing
+		.lines()
+		.filter_map(|line| line.split_once('\t'))
+		.collect();
+	let candidates = [
+		reference.to_owned(),
+		format!("refs/heads/{reference}"),
+		format!("refs/tags/{reference}^{{}}"),
+		format!("refs/tags/{reference}"),
+	];
+	candidates.iter().find_map(|candidate| {
+		refs.iter()
+			.find(|(_, name)| name == candidate)
+			.map(|(sha, _)| (*sha).to_owned())
+	})
+}
+
+/// Check if a path is a git repository, either with a worktree or bare.
 pub fn is_repository(path: &Path) -> bool {
-	path.join(".git").exists() || path.join("HEAD").exists()
+	path.join(".git").exists() || is_bare_repository(path)
+}
+
+/// Check if a path is a bare git repository (`HEAD` and `objects/` at the top level).
+pub fn is_bare_repository(path: &Path) -> bool {
+	path.join("HEAD").is_file() && path.join("objects").is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::git::git;
+
+	#[test]
+	fn test_ls_remote_commit_prefers_branches_and_peeled_tags() {
+		let listing = "aaa\trefs/heads/feature/v1\nbbb\trefs/tags/v1\nccc\trefs/tags/v1^{}\nddd\trefs/heads/v1\n";
+		assert_eq!(ls_remote_commit(listing, "v1").as_deref(), Some("ddd"));
+		assert_eq!(
+			ls_remote_commit(listing, "refs/tags/v1").as_deref(),
+			Some("bbb")
+		);
+		let tags_only = "bbb\trefs/tags/v1\nccc\trefs/tags/v1^{}\n";
+		assert_eq!(ls_remote_commit(tags_only, "v1").as_deref(), Some("ccc"));
+		assert_eq!(ls_remote_commit(listing, "v2"), None);
+	}
+
+	#[test]
+	fn test_init_creates_worktree_repository() {
+		let dir = tempfile::tempdir().unwrap();
+		init(dir.path()).unwrap();
+		assert!(is_repository(dir.path()));
+		assert!(dir.path().join(".git").is_dir());
+		assert!(!is_bare_repository(dir.path()));
+	}
+
+	#[test]
+	fn test_init_bare_is_detected() {
+		let dir = tempfile::tempdir().unwrap();
+		init_bare(dir.path()).unwrap();
+		assert!(is_repository(dir.path()));
+		assert!(is_bare_repository(dir.path()));
+	}
+
+	#[test]
+	fn test_remote_default_branch_and_commit() {
+		let upstream = tempfile::tempdir().unwrap();
+		init(upstream.path()).unwrap();
+		git()
+			.cwd(upstream.path())
+			.args(["checkout", "-q", "-b", "trunk"])
+			.run()
+			.unwrap();
+		git()
+			.cwd(upstream.path())
+			.args([
+				"-c",
+				"user.name=test",
+				"-c",
+				"user.email=test@example.com",
+				"commit",
+				"-q",
+				"--allow-empty",
+				"-m",
+				"init",
+			])
+			.run()
+			.unwrap();
+
+		assert_eq!(
+			remote_default_branch(&upstream.path().to_string_lossy()).unwrap(),
+			"trunk"
+		);
+
+		let head = git()
+			.cwd(upstream.path())
+			.args(["rev-parse", "HEAD"])
+			.stdout()
+			.unwrap();
+		let url = upstream.path().to_string_lossy();
+		assert_eq!(remote_commit(&url, "trunk").unwrap(), head);
+		assert!(remote_commit(&url, "missing").is_err());
+	}
 }
@@ -0,0 +1,147 @@
+//! Estimate the on-disk size of a sparse selection.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context, Result};
+use gix::bstr::ByteSlice;
+
+use crate::git;
+
+/// Total blob size of a set of sparse patterns, with a per-top-level-directory breakdown.
+///
+/// Sizes come from the object headers, so LFS-tracked files count at their pointer size
+/// until the LFS objects are fetched.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SizeEstimate {
+	pub total: u64,
+	pub files: usize,
+	/// Map of top-level directory (or root file name) -> bytes.
+	pub by_top_level: BTreeMap<String, u64>,
+}
+
+impl SizeEstimate {
+	fn record(&mut self, path: &str, size: u64) {
+		let top_level = path.split('/').next().unwrap_or(path);
+		self.total += size;
+		self.files += 1;
+		*self.by_top_level.entry(top_level.to_owned()).or_insert(0) += size;
+	}
+}
+
+/// Sum the blob sizes of every index entry selected by `patterns`, recursing into
+/// checked-out submodules.
+///
+/// A pattern selects the file with that exact path, or everything below it when it names a
+/// directory.
+pub fn estimate(
+	repo: &gix::Repository,
+	worktree: &gix::Worktree<'_>,
+	patterns: &BTreeSet<String>,
+) -> Result<SizeEstimate> {
+	let mut estimate = SizeEstimate::default();
+	estimate_recursive(repo, worktree, "", patterns, &mut estimate)?;
+	Ok(estimate)
+}
+
+fn estimate_recursive(
+	repo: &gix::Repository,
+	worktree: &gix::Worktree<'_>,
+	prefix: &str,
+	patterns: &BTreeSet<String>,
+	estimate: &mut SizeEstimate,
+) -> Result<()> {
+	let index = repo.open_index().with_context(|| {
+		format!(
+			"failed to load git index for repository at {}",
+			worktree.base().display()
+		)
+	})?;
+
+	for entry in index.entries() {
+		let path = entry.path(&index).to_str_lossy();
+		let full_path = if prefix.is_empty() {
+			path.to_string()
+		} else {
+			format!("{}/{}", prefix, path)
+		};
+
+		if entry.mode == gix::index::entry::Mode::COMMIT {
+			let submodule_path = worktree.base().join(path.as_ref());
+			if submodule_path.exists() && covers_subtree(patterns, &full_path) {
+				let (sub_repo, _) = git::open_repository(Some(&submodule_path))?;
+				let sub_worktree = git::require_worktree(&sub_repo)?;
+				estimate_recursive(&sub_repo, &sub_worktree, &full_path, patterns, estimate)?;
+			}
+			continue;
+		}
+
+		if is_selected(patterns, &full_path) {
+			let header = repo
+				.find_header(entry.id)
+				.with_context(|| format!("failed to read object header for {}", full_path))?;
+			estimate.record(&full_path, header.size());
+		}
+	}
+
+	Ok(())
+}
+
+/// Whether `path` is named by a pattern or lies below a directory pattern.
+fn is_selected(patterns: &BTreeSet<String>, path: &str) -> bool {
+	patterns.contains(path)
+		|| patterns.iter().any(|pattern| {
+			let dir = pattern.trim_end_matches('/');
+			path.strip_prefix(dir)
+				.is_some_and(|rest| rest.starts_with('/'))
+		})
+}
+
+/// Whether any pattern could select something inside the directory `dir`.
+fn covers_subtree(patterns: &BTreeSet<String>, dir: &str) -> bool {
+	is_selected(patterns, dir)
+		|| patterns.iter().any(|pattern| {
+			pattern
+				.strip_prefix(dir)
+				.is_some_and(|rest| rest.starts_with('/'))
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+
+	#[test]
+	fn test_estimate_sums_files_and_directory_patterns() {
+		let dir = tempfile::tempdir().unwrap();
+		git::repository::init(dir.path()).unwrap();
+		for (path, contents) in [
+			("assets/a.bin", "12345"),
+			("assets/deep/b.bin", "123"),
+			("docs/readme.md", "1234567"),
+			("root.txt", "12"),
+		] {
+			let full = dir.path().join(path);
+			fs::create_dir_all(full.parent().unwrap()).unwrap();
+			fs::write(full, contents).unwrap();
+		}
+		git::git()
+			.cwd(dir.path())
+			.args(["add", "-A"])
+			.run()
+			.unwrap();
+
+		let (repo, _) = git::open_repository(Some(dir.path())).unwrap();
+		let worktree = git::require_worktree(&repo).unwrap();
+		let patterns = BTreeSet::from(["assets/".to_owned(), "root.txt".to_owned()]);
+
+		let estimate = estimate(&repo, &worktree, &patterns).unwrap();
+		assert_eq!(estimate.total, 10);
+		assert_eq!(estimate.files, 3);
+		assert_eq!(
+			estimate.by_top_level,
+			BTreeMap::from([("assets".to_owned(), 8), ("root.txt".to_owned(), 2)])
+		);
+	}
+}
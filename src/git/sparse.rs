@@ -1,13 +1,21 @@
 //! Sparse checkout operations.
 
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use walkdir::WalkDir;
 
 use super::git;
+use crate::atomic;
 
 /// Configure sparse checkout for a repository.
+///
+/// Comments already in `info/sparse-checkout` survive the rewrite: the header (everything
+/// above the first blank line before the first pattern) stays at the top, trailing comments
+/// stay at the end, and the comment and blank lines directly above a pattern move with it
+/// when it is kept and are dropped with it otherwise.
 pub fn configure(git_dir: &Path, patterns: &[String]) -> Result<()> {
 	// Enable sparse checkout
 	git()
@@ -17,12 +25,58 @@ pub fn configure(git_dir: &Path, patterns: &[String]) -> Result<()> {
 
 	// Write sparse-checkout file
 	let sparse_file = git_dir.join("info/sparse-checkout");
+	let existing = if sparse_file.exists() {
+		fs::read_to_string(&sparse_file)?
+	} else {
+		String::new()
+	};
 	fs::create_dir_all(git_dir.join("info"))?;
-	fs::write(&sparse_file, patterns.join("\n") + "\n")?;
+	atomic::write(&sparse_file, merge_comments(&existing, patterns))?;
 
 	Ok(())
 }
 
+/// Lay out `patterns` with the comments of an existing sparse-checkout file, as described on
+/// [`configure`].
+fn merge_comments(existing: &str, patterns: &[String]) -> String {
+	let mut header = Vec::new();
+	let mut leading: HashMap<&str, Vec<&str>> = HashMap::new();
+	let mut pending = Vec::new();
+	for line in existing.lines() {
+		if is_comment(line) || line.trim().is_empty() {
+			pending.push(line);
+			continue;
+		}
+		if leading.is_empty()
+			&& header.is_empty()
+			&& let Some(blank) = pending.iter().rposition(|line| line.trim().is_empty())
+		{
+			header = pending.drain(..=blank).collect();
+		}
+		leading
+			.entry(line.trim())
+			.or_insert_with(|| std::mem::take(&mut pending));
+		pending.clear();
+	}
+	while pending.last().is_some_and(|line| line.trim().is_empty()) {
+		pending.pop();
+	}
+
+	let mut content = String::new();
+	let lines = header
+		.into_iter()
+		.chain(patterns.iter().flat_map(|pattern| {
+			let comments = leading.get(pattern.as_str()).into_iter().flatten().copied();
+			comments.chain(std::iter::once(pattern.as_str()))
+		}))
+		.chain(pending);
+	for line in lines {
+		content.push_str(line);
+		content.push('\n');
+	}
+	content
+}
+
 /// Materialize sparse checkout files into the worktree.
 pub fn checkout(git_dir: &Path, worktree: &Path) -> Result<()> {
 	// Run read-tree to update the index with sparse patterns
@@ -39,3 +93,254 @@ pub fn checkout(git_dir: &Path, worktree: &Path) -> Result<()> {
 		.args(["checkout-index", "--all", "--force"])
 		.run()
 }
+
+/// Read the patterns currently written to `info/sparse-checkout`.
+///
+/// A missing file yields an empty list; blank lines and comments are skipped.
+pub fn read_patterns(git_dir: &Path) -> Result<Vec<String>> {
+	let sparse_file = git_dir.join("info/sparse-checkout");
+	if !sparse_file.exists() {
+		return Ok(Vec::new());
+	}
+
+	let content = fs::read_to_string(&sparse_file)?;
+	Ok(content
+		.lines()
+		.filter(|line| !is_comment(line))
+		.map(str::trim)
+		.filter(|line| !line.is_empty())
+		.map(String::from)
+		.collect())
+}
+
+/// Whether a sparse-checkout line is a comment. A literal leading `#` is written `\#`.
+fn is_comment(line: &str) -> bool {
+	line.trim_start().starts_with('#')
+}
+
+/// Non-cone sparse-checkout patterns, matched the way git decides which files to check out.
+pub struct SparsePatterns(Vec<gix::glob::Pattern>);
+
+impl SparsePatterns {
+	/// Parse sparse-checkout lines, skipping blank lines and comments.
+	pub fn new(lines: &[String]) -> Self {
+		Self(
+			lines
+				.iter()
+				.filter(|line| !is_comment(line))
+				.filter_map(|line| gix::glob::parse(line.trim().as_bytes()))
+				.collect(),
+		)
+	}
+
+	/// Whether the repository-relative file `path` is checked out.
+	///
+	/// The last pattern matching the file decides; when none does, the closest parent
+	/// directory that a pattern matches decides instead, so `/dir/` takes in everything below
+	/// it unless a later `!` pattern excludes part of it.
+	pub fn includes(&self, path: &str) -> bool {
+		let mut path = path.trim_start_matches('/');
+		let mut is_dir = false;
+		loop {
+			let basename_start = path.rfind('/').map(|slash| slash + 1);
+			let decision = self.0.iter().rev().find(|pattern| {
+				pattern.matches_repo_relative_path(
+					path.into(),
+					basename_start,
+					Some(is_dir),
+					gix::glob::pattern::Case::Sensitive,
+					gix::glob::wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+				)
+			});
+			if let Some(pattern) = decision {
+				return !pattern.is_negative();
+			}
+			match path.rfind('/') {
+				Some(slash) => path = &path[..slash],
+				None => return false,
+			}
+			is_dir = true;
+		}
+	}
+}
+
+/// Patterns added and removed between two sparse-checkout pattern lists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatternDiff {
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+}
+
+impl PatternDiff {
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty()
+	}
+}
+
+/// Compare `old` and `new` pattern lists, ignoring order and duplicates.
+pub fn diff_patterns(old: &[String], new: &[String]) -> PatternDiff {
+	let old: BTreeSet<&String> = old.iter().collect();
+	let new: BTreeSet<&String> = new.iter().collect();
+
+	PatternDiff {
+		added: new.difference(&old).map(|p| p.to_string()).collect(),
+		removed: old.difference(&new).map(|p| p.to_string()).collect(),
+	}
+}
+
+/// Return the patterns that name a concrete file but are missing from `worktree`.
+///
+/// Patterns with glob characters or a trailing `/` are skipped since they don't denote a
+/// single file, and so are `!` negations, which exclude files rather than name them.
+pub fn missing_files(worktree: &Path, patterns: &[String]) -> Vec<PathBuf> {
+	patterns
+		.iter()
+		.filter(|pattern| !pattern.starts_with('!'))
+		.map(|pattern| pattern.trim_start_matches('/'))
+		.filter(|pattern| {
+			!pattern.is_empty() && !pattern.ends_with('/') && !pattern.contains(['*', '?', '['])
+		})
+		.map(|pattern| worktree.join(pattern))
+		.filter(|path| !path.exists())
+		.collect()
+}
+
+/// Count the files checked out in `worktree`, not descending into `.git` or nested
+/// repositories' git directories.
+pub fn materialized_files(worktree: &Path) -> Result<usize> {
+	let mut count = 0;
+	for entry in WalkDir::new(worktree)
+		.into_iter()
+		.filter_entry(|entry| entry.file_name() != ".git")
+	{
+		if !entry?.file_type().is_dir() {
+			count += 1;
+		}
+	}
+	Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_read_patterns_round_trips_configure_and_diffs() {
+		let dir = tempfile::tempdir().unwrap();
+		assert!(read_patterns(dir.path()).unwrap().is_empty());
+
+		fs::create_dir_all(dir.path().join("info")).unwrap();
+		fs::write(
+			dir.path().join("info/sparse-checkout"),
+			"# generated\n/a/**\n\n/b/**\n",
+		)
+		.unwrap();
+		let old = read_patterns(dir.path()).unwrap();
+		assert_eq!(old, ["/a/**", "/b/**"]);
+
+		let new = ["/b/**", "/c/**"].map(String::from);
+		let diff = diff_patterns(&old, &new);
+		assert_eq!(diff.added, ["/c/**"]);
+		assert_eq!(diff.removed, ["/a/**"]);
+		assert!(diff_patterns(&new, &new).is_empty());
+	}
+
+	#[test]
+	fn test_configure_keeps_comments_and_skips_them_when_reading() {
+		let dir = tempfile::tempdir().unwrap();
+		crate::git::repository::init_bare(dir.path()).unwrap();
+		fs::write(
+			dir.path().join("info/sparse-checkout"),
+			"# managed by git-sparta\n\n/a/**\n  # indented note\n\\#literal\n\n",
+		)
+		.unwrap();
+		assert_eq!(read_patterns(dir.path()).unwrap(), ["/a/**", "\\#literal"]);
+
+		let new = ["/b/**", "/c/**"].map(String::from);
+		configure(dir.path(), &new).unwrap();
+
+		assert_eq!(
+			fs::read_to_string(dir.path().join("info/sparse-checkout")).unwrap(),
+			"# managed by git-sparta\n\n/b/**\n/c/**\n"
+		);
+		assert_eq!(read_patterns(dir.path()).unwrap(), new);
+	}
+
+	#[test]
+	fn test_configure_keeps_comments_next_to_their_patterns() {
+		let existing = "# header\n\n# keep a\n/a/**\n\n# old b\n/b/**\n# trailing\n\n";
+		let new = ["/a/**", "/c/**"].map(String::from);
+		assert_eq!(
+			merge_comments(existing, &new),
+			"# header\n\n# keep a\n/a/**\n/c/**\n# trailing\n"
+		);
+
+		// Without a blank line the first comment belongs to the first pattern.
+		assert_eq!(merge_comments("# only a\n/a/**\n", &new[1..]), "/c/**\n");
+		assert_eq!(merge_comments("", &new), "/a/**\n/c/**\n");
+	}
+
+	#[test]
+	fn test_missing_files_skips_globs_directories_and_negations() {
+		let dir = tempfile::tempdir().unwrap();
+		fs::create_dir_all(dir.path().join("assets")).unwrap();
+		fs::write(dir.path().join("assets/present.txt"), "").unwrap();
+
+		let patterns = [
+			"assets/present.txt",
+			"assets/absent.txt",
+			"/assets/also-absent.txt",
+			"assets/*.png",
+			"docs/",
+			"!assets/excluded.txt",
+			"!/assets/also-excluded.txt",
+		]
+		.map(String::from);
+
+		assert_eq!(
+			missing_files(dir.path(), &patterns),
+			[
+				dir.path().join("assets/absent.txt"),
+				dir.path().join("assets/also-absent.txt"),
+			]
+		);
+	}
+
+	#[test]
+	fn test_sparse_patterns_follow_git_matching() {
+		let sparse = SparsePatterns::new(
+			&[
+				"# assets",
+				"/a/",
+				"!/a/secret.txt",
+				"docs/*.md",
+				"",
+				"b/x.txt",
+			]
+			.map(String::from),
+		);
+
+		assert!(sparse.includes("a/one.txt"));
+		assert!(sparse.includes("a/deep/two.txt"));
+		assert!(!sparse.includes("a/secret.txt"));
+		// `/a/` is anchored, so a nested `a/` stays out.
+		assert!(!sparse.includes("b/a/z.txt"));
+		assert!(sparse.includes("b/x.txt"));
+		assert!(!sparse.includes("b/y.txt"));
+		assert!(sparse.includes("docs/readme.md"));
+		assert!(!sparse.includes("docs/notes.txt"));
+		assert!(!sparse.includes("# assets"));
+	}
+
+	#[test]
+	fn test_materialized_files_skips_git_dir() {
+		let dir = tempfile::tempdir().unwrap();
+		fs::write(dir.path().join(".git"), "gitdir: ../modules/sub\n").unwrap();
+		assert_eq!(materialized_files(dir.path()).unwrap(), 0);
+
+		fs::create_dir_all(dir.path().join("a/b")).unwrap();
+		fs::write(dir.path().join("a/b/file.txt"), "").unwrap();
+		fs::write(dir.path().join("top.txt"), "").unwrap();
+		assert_eq!(materialized_files(dir.path()).unwrap(), 2);
+	}
+}
@@ -1,14 +1,15 @@
 //! Git submodule operations.
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use dunce::canonicalize;
+use gix::bstr::ByteSlice;
 use walkdir::WalkDir;
 
 use super::git;
-use crate::output;
+use crate::atomic;
 
 /// Convert a path to a Unix-style string (forward slashes).
 pub fn path_to_unix_string(path: &Path) -> String {
@@ -133,7 +134,13 @@ pub fn add_gitlink(repo_path: &Path, submodule_path: &Path, commit_sha: &str) ->
 }
 
 /// Get the commit SHA for a gitlink in the index.
+///
+/// The index is read with gix; `git ls-files` is only used if gix can't open it.
 pub fn get_gitlink_sha(repo_path: &Path, submodule_path: &Path) -> Result<String> {
+	if let Ok(sha) = gitlink_sha_from_index(repo_path, submodule_path) {
+		return sha.context("no gitlink found in index");
+	}
+
 	let output = git()
 		.cwd(repo_path)
 		.args(["ls-files", "--stage", "--"])
@@ -148,55 +155,113 @@ pub fn get_gitlink_sha(repo_path: &Path, submodule_path: &Path) -> Result<String
 		.context("no gitlink found in index")
 }
 
+/// Look up the gitlink for `submodule_path` in the index of the repository at `repo_path`.
+fn gitlink_sha_from_index(repo_path: &Path, submodule_path: &Path) -> Result<Option<String>> {
+	let repo = gix::open(repo_path)?;
+	let index = repo.open_index()?;
+	let path = path_to_unix_string(submodule_path);
+	Ok(index
+		.entry_by_path(path.as_bytes().as_bstr())
+		.filter(|entry| entry.mode == gix::index::entry::Mode::COMMIT)
+		.map(|entry| entry.id.to_string()))
+}
+
 /// Set up the modules directory (bare repository) for a submodule.
-pub fn setup_modules_directory(modules_path: &Path, mirror_path: Option<&Path>) -> Result<()> {
+///
+/// Problems with the mirror are pushed onto `warnings` (see [`configure_alternates`]).
+pub fn setup_modules_directory(
+	modules_path: &Path,
+	mirror_path: Option<&Path>,
+	warnings: &mut Vec<String>,
+) -> Result<()> {
 	if !modules_path.exists() {
-		output::note(&format!(
-			"Initializing bare repository at {}",
-			modules_path.display()
-		));
 		super::repository::init_bare(modules_path)?;
 	}
 
 	// Configure alternates if using mirror
 	if let Some(mirror) = mirror_path {
-		configure_alternates(modules_path, mirror)?;
+		configure_alternates(modules_path, mirror, warnings)?;
 	}
 
 	Ok(())
 }
 
+/// Locate a mirror's object store: `.git/objects` for a clone with a working tree, or
+/// `objects` at the top level for a bare mirror.
+pub fn mirror_objects_dir(mirror_path: &Path) -> Option<PathBuf> {
+	[
+		mirror_path.join(".git/objects"),
+		mirror_path.join("objects"),
+	]
+	.into_iter()
+	.find(|objects| objects.is_dir())
+}
+
 /// Configure git alternates to share objects with a mirror.
-pub fn configure_alternates(git_dir: &Path, mirror_path: &Path) -> Result<()> {
-	let mirror_objects = mirror_path.join(".git/objects");
-	if !mirror_objects.exists() {
+///
+/// A mirror without an object store is reported on `warnings` and otherwise ignored, so
+/// the fetch still works, just without sharing objects.
+///
+/// Existing entries are compared after canonicalization, so a trailing slash or a
+/// symlinked mirror path does not produce a duplicate line on re-run.
+pub fn configure_alternates(
+	git_dir: &Path,
+	mirror_path: &Path,
+	warnings: &mut Vec<String>,
+) -> Result<()> {
+	let Some(mirror_objects) = mirror_objects_dir(mirror_path) else {
+		warnings.push(format!(
+			"Mirror at {} has no git object store (neither .git/objects nor objects); \
+			 check SHARED_MIRROR_PATH. Continuing without alternates.",
+			mirror_path.display()
+		));
 		return Ok(());
-	}
-
-	let alternates_dir = git_dir.join("objects/info");
-	fs::create_dir_all(&alternates_dir)?;
-	let alternates_file = alternates_dir.join("alternates");
-
+	};
+	let mirror_objects = canonicalize(&mirror_objects).with_context(|| {
+		format!(
+			"failed to resolve mirror objects at {}",
+			mirror_objects.display()
+		)
+	})?;
+
+	let objects_dir = git_dir.join("objects");
+	let alternates_file = objects_dir.join("info/alternates");
 	let current = if alternates_file.exists() {
 		fs::read_to_string(&alternates_file)?
 	} else {
 		String::new()
 	};
 
-	let mirror_path_str = mirror_objects.display().to_string();
-	if !current.lines().any(|line| line == mirror_path_str) {
-		let new_content = if current.is_empty() {
-			format!("{}\n", mirror_path_str)
-		} else {
-			format!("{}{}\n", current, mirror_path_str)
-		};
-		fs::write(&alternates_file, new_content)?;
-		output::note("Configured git alternates from mirror");
+	let already_present = current
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.any(|line| resolve_alternate(&objects_dir, line) == mirror_objects);
+	if already_present {
+		return Ok(());
 	}
 
+	if let Some(alternates_dir) = alternates_file.parent() {
+		fs::create_dir_all(alternates_dir)?;
+	}
+	let mut new_content = current;
+	if !new_content.is_empty() && !new_content.ends_with('\n') {
+		new_content.push('\n');
+	}
+	new_content.push_str(&mirror_objects.display().to_string());
+	new_content.push('\n');
+	atomic::write(&alternates_file, new_content)?;
+
 	Ok(())
 }
 
+/// Resolve an alternates entry to a comparable path. Relative entries are relative to the
+/// objects directory; entries that no longer exist are kept as written, minus trailing slashes.
+fn resolve_alternate(objects_dir: &Path, entry: &str) -> PathBuf {
+	let path = objects_dir.join(entry);
+	canonicalize(&path).unwrap_or_else(|_| PathBuf::from(entry.trim_end_matches('/')))
+}
+
 /// Configure the modules repository with worktree settings.
 pub fn configure_modules_repo(modules_path: &Path, worktree_path: &Path) -> Result<()> {
 	git()
@@ -223,7 +288,6 @@ pub fn add_remote_if_missing(git_dir: &Path, name: &str, url: &str) -> Result<bo
 			.git_dir(git_dir)
 			.args(["remote", "add", name, url])
 			.run()?;
-		output::note(&format!("Added remote '{}'", name));
 		Ok(true)
 	} else {
 		Ok(false)
@@ -270,3 +334,76 @@ pub fn has_commit(git_dir: &Path, commit_sha: &str) -> Result<bool> {
 		.args(["cat-file", "-e", commit_sha])
 		.ok()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_get_gitlink_sha_reads_index() {
+		let dir = tempfile::tempdir().unwrap();
+		crate::git::repository::init(dir.path()).unwrap();
+		let sha = "0123456789abcdef0123456789abcdef01234567";
+		add_gitlink(dir.path(), Path::new("deps/assets"), sha).unwrap();
+
+		assert_eq!(
+			gitlink_sha_from_index(dir.path(), Path::new("deps/assets")).unwrap(),
+			Some(sha.to_owned())
+		);
+		assert_eq!(
+			get_gitlink_sha(dir.path(), Path::new("deps/assets")).unwrap(),
+			sha
+		);
+		assert!(get_gitlink_sha(dir.path(), Path::new("deps/other")).is_err());
+	}
+
+	#[test]
+	fn test_configure_alternates_is_idempotent() {
+		let dir = tempfile::tempdir().unwrap();
+		let mirror = dir.path().join("mirror");
+		fs::create_dir_all(mirror.join(".git/objects")).unwrap();
+		let git_dir = dir.path().join("modules/assets");
+		fs::create_dir_all(git_dir.join("objects")).unwrap();
+
+		let mut warnings = Vec::new();
+		configure_alternates(&git_dir, &mirror, &mut warnings).unwrap();
+		// A trailing slash names the same mirror and must not add a second entry.
+		configure_alternates(&git_dir, &dir.path().join("mirror/"), &mut warnings).unwrap();
+		assert!(warnings.is_empty());
+
+		let alternates = fs::read_to_string(git_dir.join("objects/info/alternates")).unwrap();
+		assert_eq!(alternates.lines().count(), 1);
+	}
+
+	#[test]
+	fn test_configure_alternates_uses_bare_mirror_objects() {
+		let dir = tempfile::tempdir().unwrap();
+		let mirror = dir.path().join("mirror.git");
+		crate::git::repository::init_bare(&mirror).unwrap();
+		let git_dir = dir.path().join("modules/assets");
+
+		configure_alternates(&git_dir, &mirror, &mut Vec::new()).unwrap();
+
+		let alternates = fs::read_to_string(git_dir.join("objects/info/alternates")).unwrap();
+		assert_eq!(
+			alternates.trim_end(),
+			canonicalize(mirror.join("objects"))
+				.unwrap()
+				.display()
+				.to_string()
+		);
+	}
+
+	#[test]
+	fn test_configure_alternates_skips_missing_mirror() {
+		let dir = tempfile::tempdir().unwrap();
+		let git_dir = dir.path().join("modules/assets");
+
+		let mut warnings = Vec::new();
+		configure_alternates(&git_dir, &dir.path().join("missing"), &mut warnings).unwrap();
+
+		assert!(!git_dir.join("objects/info").exists());
+		assert_eq!(warnings.len(), 1);
+		assert!(warnings[0].contains("no git object store"));
+	}
+}
@@ -1,5 +1,8 @@
+pub mod atomic;
 pub mod commands;
 pub mod config;
 pub mod git;
 pub mod output;
 pub mod picker;
+pub mod project_config;
+pub mod remote;
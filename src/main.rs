@@ -1,7 +1,10 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{Parser, Subcommand};
-use git_sparta::commands::{generate, setup, teardown};
+use git_sparta::commands::{generate, setup, teardown, update};
+use git_sparta::config::{Config, DEFAULT_ATTRIBUTE};
+use git_sparta::git::attributes::{MatchMode, PathFilter};
+use git_sparta::project_config::{OutputFormat, ProjectConfig};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -22,7 +25,8 @@ enum Command {
 	/// Generate sparse-checkout patterns for a given project tag.
 	///
 	/// When run without a tag, an interactive picker displays all available
-	/// tags/attributes found in the repository for you to choose from.
+	/// tags/attributes found in the repository for you to choose from. In the picker,
+	/// Home/End jump to the first/last tag; Ctrl+a/Ctrl+e move the query cursor.
 	GenerateSparseList {
 		/// Project tag filter (substring match). If omitted, shows an interactive picker.
 		tag: Option<String>,
@@ -32,9 +36,54 @@ enum Command {
 		/// Repository directory (defaults to current working directory).
 		#[arg(long)]
 		repo: Option<PathBuf>,
-		/// Git attribute name to search for tags.
-		#[arg(long, short = 'a', default_value = "projects")]
-		attribute: String,
+		/// Git attribute name to search for tags [default: --config-dir's ATTRIBUTE, else
+		/// .sparta.toml's `attribute`, else projects].
+		#[arg(long, short = 'a')]
+		attribute: Option<String>,
+		/// Directory with a setup JSON configuration; its PROJECT_TAG, SHARED_MIRROR_PATH and
+		/// ATTRIBUTE are used when no tag, --repo or --attribute is given.
+		#[arg(long)]
+		config_dir: Option<PathBuf>,
+		/// Read newline-separated tags from stdin and print the union of their patterns.
+		#[arg(long, conflicts_with = "tag")]
+		stdin: bool,
+		/// Preselect the tags whose files are all in this existing sparse checkout (a worktree
+		/// or git dir) in a multi-select picker; with --yes those tags are used directly.
+		#[arg(long, value_name = "PATH", conflicts_with_all = ["tag", "stdin"])]
+		from_sparse: Option<PathBuf>,
+		/// Print the estimated size of the selected files, per top-level directory.
+		#[arg(long)]
+		size: bool,
+		/// Terminate each printed pattern with NUL instead of a newline (for `xargs -0`).
+		#[arg(long)]
+		print0: bool,
+		/// Print the selected tag as the first line of output, before the patterns. Several
+		/// tags (from --stdin or --from-sparse) are joined with commas on that line.
+		#[arg(long)]
+		print_tag: bool,
+		/// Write a JSON manifest of the matched files and their tags to this path.
+		#[arg(long, value_name = "PATH")]
+		manifest: Option<PathBuf>,
+		/// Collapse directories whose files are all selected into a single `/dir/` pattern.
+		#[arg(long)]
+		compact: bool,
+		/// Prefix each pattern with the number of matched files it covers and a tab; only
+		/// --compact directory patterns cover more than one.
+		#[arg(long)]
+		counts: bool,
+		/// How a tag is compared with each file's tags [default: .sparta.toml's `match`, else
+		/// substring].
+		#[arg(long = "match", value_enum)]
+		match_mode: Option<MatchMode>,
+		/// Don't treat a bare attribute as `global`, and match a literal `global` tag like any other.
+		#[arg(long)]
+		no_global: bool,
+		/// Only keep files matching this glob (repeatable).
+		#[arg(long, value_name = "GLOB")]
+		include: Vec<String>,
+		/// Drop files matching this glob (repeatable).
+		#[arg(long, value_name = "GLOB")]
+		exclude: Vec<String>,
 	},
 	/// Configure a sparse submodule clone according to JSON metadata.
 	SetupSubmodule {
@@ -44,6 +93,27 @@ enum Command {
 		/// Automatically confirm interactive prompts.
 		#[arg(long, short = 'y')]
 		yes: bool,
+		/// Check that every file named by a sparse pattern exists after checkout (default).
+		#[arg(long, overrides_with = "no_verify")]
+		verify: bool,
+		/// Skip the post-checkout verification.
+		#[arg(long, overrides_with = "verify")]
+		no_verify: bool,
+		/// Git attribute name to search for tags (overrides the config's ATTRIBUTE; default `projects`).
+		#[arg(long, short = 'a')]
+		attribute: Option<String>,
+		/// Keep `.bak` copies of .gitmodules and the local git config before editing them.
+		#[arg(long)]
+		backup: bool,
+	},
+	/// Fetch the latest commit of a configured sparse submodule and re-materialize it.
+	UpdateSubmodule {
+		/// Directory that contains the JSON configuration and .gitmodules file (defaults to current dir).
+		#[arg(long)]
+		config_dir: Option<PathBuf>,
+		/// Automatically confirm interactive prompts.
+		#[arg(long, short = 'y')]
+		yes: bool,
 	},
 	/// Remove a previously configured sparse submodule clone.
 	TeardownSubmodule {
@@ -53,6 +123,9 @@ enum Command {
 		/// Automatically confirm interactive prompts.
 		#[arg(long, short = 'y')]
 		yes: bool,
+		/// Keep `.bak` copies of .gitmodules and the local git config before editing them.
+		#[arg(long)]
+		backup: bool,
 	},
 }
 
@@ -64,8 +137,75 @@ fn main() -> anyhow::Result<()> {
 			yes,
 			repo,
 			attribute,
-		} => generate::run(tag.as_deref(), yes, repo.as_deref(), &attribute),
-		Command::SetupSubmodule { config_dir, yes } => setup::run(config_dir.as_deref(), yes),
-		Command::TeardownSubmodule { config_dir, yes } => teardown::run(config_dir.as_deref(), yes),
+			config_dir,
+			stdin,
+			from_sparse,
+			size,
+			print0,
+			print_tag,
+			manifest,
+			compact,
+			counts,
+			match_mode,
+			no_global,
+			include,
+			exclude,
+		} => {
+			// Flags override the setup config's ATTRIBUTE, then the project's .sparta.toml, then
+			// the built-in defaults; the setup config already folds in its own .sparta.toml.
+			let config = config_dir.as_deref().map(Config::load).transpose()?;
+			let project = ProjectConfig::discover(
+				repo.as_deref()
+					.or(config_dir.as_deref())
+					.unwrap_or_else(|| Path::new(".")),
+			)?;
+			let attribute = attribute
+				.or_else(|| config.as_ref().map(|config| config.attribute.clone()))
+				.or_else(|| project.attribute.clone())
+				.unwrap_or_else(|| DEFAULT_ATTRIBUTE.to_owned());
+			let mut pattern_options = project.pattern_options();
+			pattern_options.filter = PathFilter::new(&include, &exclude)?;
+			pattern_options.treat_set_as_global = !no_global;
+			if let Some(match_mode) = match_mode {
+				pattern_options.matcher = match_mode.matcher();
+			}
+
+			generate::run(generate::Options {
+				tag: tag.as_deref(),
+				auto_yes: yes,
+				repo_dir: repo.as_deref(),
+				attribute: &attribute,
+				config: config.as_ref(),
+				stdin,
+				from_sparse: from_sparse.as_deref(),
+				size,
+				print0: print0 || project.output == Some(OutputFormat::Nul),
+				print_tag,
+				manifest: manifest.as_deref(),
+				compact,
+				counts,
+				pattern_options,
+			})
+		}
+		Command::SetupSubmodule {
+			config_dir,
+			yes,
+			verify: _,
+			no_verify,
+			attribute,
+			backup,
+		} => setup::run(
+			config_dir.as_deref(),
+			yes,
+			!no_verify,
+			attribute.as_deref(),
+			backup,
+		),
+		Command::UpdateSubmodule { config_dir, yes } => update::run(config_dir.as_deref(), yes),
+		Command::TeardownSubmodule {
+			config_dir,
+			yes,
+			backup,
+		} => teardown::run(config_dir.as_deref(), yes, backup),
 	}
 }
@@ -1,4 +1,4 @@
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 
 use anyhow::Result;
 use owo_colors::OwoColorize;
@@ -25,10 +25,47 @@ pub fn bullet_list(lines: impl IntoIterator<Item = String>) {
 	}
 }
 
+/// What [`confirm_with`] does when stdin is not a terminal, so nobody can answer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonInteractive {
+	/// Fail with an error asking for `--yes` (default).
+	#[default]
+	Error,
+	/// Answer with the prompt's default.
+	UseDefault,
+}
+
+/// Ask a yes/no question on stderr; errors instead of blocking when stdin is not a terminal.
 pub fn confirm(prompt: &str, default_yes: bool, auto_yes: bool) -> Result<bool> {
+	confirm_with(prompt, default_yes, auto_yes, NonInteractive::default())
+}
+
+/// Like [`confirm`], with the non-terminal behavior chosen by `non_interactive`.
+pub fn confirm_with(
+	prompt: &str,
+	default_yes: bool,
+	auto_yes: bool,
+	non_interactive: NonInteractive,
+) -> Result<bool> {
 	if auto_yes {
 		return Ok(true);
 	}
+	if !io::stdin().is_terminal() {
+		return match non_interactive {
+			NonInteractive::UseDefault => {
+				let answer = if default_yes { "yes" } else { "no" };
+				note(&format!(
+					"{} (stdin is not a terminal; answering {})",
+					prompt, answer
+				));
+				Ok(default_yes)
+			}
+			NonInteractive::Error => anyhow::bail!(
+				"cannot confirm \"{}\": stdin is not a terminal; pass --yes to proceed",
+				prompt
+			),
+		};
+	}
 
 	let hint = if default_yes { "[Y/n]" } else { "[y/N]" };
 	eprint!("{} {} ", prompt.bold(), hint.dimmed());
@@ -54,3 +91,19 @@ pub fn success(message: &str) {
 pub fn warn(message: &str) {
 	eprintln!("{}", message.yellow().bold());
 }
+
+/// Format a byte count with a binary unit, e.g. `1.5 MiB`.
+pub fn human_size(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+	let mut value = bytes as f64;
+	let mut unit = 0;
+	while value >= 1024.0 && unit < UNITS.len() - 1 {
+		value /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{} {}", bytes, UNITS[0])
+	} else {
+		format!("{:.1} {}", value, UNITS[unit])
+	}
+}
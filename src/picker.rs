@@ -1,7 +1,57 @@
+//! Interactive tag/file picker built on `nucleo-picker`.
+//!
+//! Queries use fzf-style syntax, parsed per whitespace-separated term:
+//!
+//! | Term     | Meaning                         |
+//! |----------|---------------------------------|
+//! | `foo`    | fuzzy match                     |
+//! | `'foo`   | exact substring match           |
+//! | `!foo`   | exclude lines containing `foo`  |
+//!
+//! Terms are matched against the whole rendered line, icon prefix (see [`IconStyle`]) and
+//! trailing count/tags included, because nucleo-picker matches exactly what it displays. nucleo
+//! still parses `^foo` and `foo$`, but they anchor to those decorations rather than to the name
+//! or path, so they are not useful here.
+//!
+//! On top of nucleo-picker's default keybindings, `PageUp`/`PageDown` move the selection by a
+//! screenful, `Home`/`End` jump to the first/last match, and `Alt+Enter` accepts the typed
+//! query without choosing an entry. `Home`/`End` no longer move the query cursor, so use
+//! `Ctrl+a`/`Ctrl+e` for that. The query is edited readline-style: `Ctrl+u` clears to the start,
+//! `Ctrl+w` deletes the previous word and `Ctrl+k` clears to the end.
+//!
+//! With [`SearchUi::with_multi_select`], `Tab` marks or unmarks the highlighted entry and
+//! `Enter` accepts every marked entry (or the highlighted one if none is marked). Marks show as
+//! `●` in a leading `○` column that is only drawn, never matched, so marking doesn't change
+//! how entries score or which ones a query finds.
+//!
+//! Matching can be tuned with [`SearchUi::with_case_matching`],
+//! [`SearchUi::with_normalization`], [`SearchUi::with_prefer_prefix`] and
+//! [`SearchUi::with_path_matching`], which map onto nucleo's pattern options and
+//! [`nucleo::Config`](nucleo_picker::nucleo::Config). Everything else keeps nucleo's defaults.
+//! [`SearchUi::rank`] applies the same matching without opening the terminal UI.
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
 use anyhow::{Result, anyhow};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use devicons::FileIcon;
+use directories::{BaseDirs, ProjectDirs};
 use nucleo_picker::error::PickError;
+use nucleo_picker::event::{Event, MatchListEvent, PromptEvent, StdinReader, keybind_default};
+use nucleo_picker::nucleo::pattern::Pattern;
+pub use nucleo_picker::nucleo::pattern::{CaseMatching, Normalization};
+use nucleo_picker::nucleo::{Matcher, Utf32Str};
 use nucleo_picker::{PickerOptions, Render};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AttributeRow {
@@ -48,12 +98,25 @@ impl UiConfig {
 	}
 }
 
+/// Order in which entries are listed before any query narrows them down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortBy {
+	/// Attributes by name.
+	#[default]
+	Name,
+	/// Attributes by descending match count (ties by name).
+	CountDesc,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct SearchData {
 	context: Option<String>,
 	initial_query: Option<String>,
 	attributes: Vec<AttributeRow>,
 	files: Vec<FileRow>,
+	sort: SortBy,
+	sort_files: bool,
+	files_first: bool,
 }
 
 impl SearchData {
@@ -61,6 +124,16 @@ impl SearchData {
 		Self::default()
 	}
 
+	/// Build data holding one untagged file entry per path, e.g. from `git ls-files` output.
+	pub fn from_paths(paths: impl IntoIterator<Item = impl Into<String>>) -> Self {
+		Self::new().with_files(
+			paths
+				.into_iter()
+				.map(|path| FileRow::new(path, Vec::<String>::new()))
+				.collect(),
+		)
+	}
+
 	pub fn with_context(mut self, context: impl Into<String>) -> Self {
 		self.context = Some(context.into());
 		self
@@ -80,12 +153,58 @@ impl SearchData {
 		self.files = files;
 		self
 	}
+
+	pub fn with_sort(mut self, sort: SortBy) -> Self {
+		self.sort = sort;
+		self
+	}
+
+	/// Sort files by path instead of keeping the order they were given in.
+	pub fn with_sorted_files(mut self, sort_files: bool) -> Self {
+		self.sort_files = sort_files;
+		self
+	}
+
+	/// List files ahead of attributes, e.g. when confirming a tag's file selection.
+	pub fn with_files_first(mut self, files_first: bool) -> Self {
+		self.files_first = files_first;
+		self
+	}
 }
 
 pub struct SearchUi {
 	data: SearchData,
 	ui_config: UiConfig,
 	input_title: Option<String>,
+	icons: IconStyle,
+	persist_query: bool,
+	frame_interval: Option<Duration>,
+	match_details: bool,
+	window_title: bool,
+	label_width: usize,
+	matching: MatchOptions,
+	multi_select: bool,
+	marked: Vec<SearchSelection>,
+}
+
+/// The nucleo options [`SearchUi`] exposes; the defaults are nucleo's own.
+#[derive(Clone, Copy, Debug, Default)]
+struct MatchOptions {
+	case_matching: CaseMatching,
+	normalization: Normalization,
+	prefer_prefix: bool,
+	path_matching: bool,
+}
+
+impl MatchOptions {
+	fn config(&self) -> nucleo_picker::nucleo::Config {
+		let mut config = nucleo_picker::nucleo::Config::DEFAULT;
+		if self.path_matching {
+			config.set_match_paths();
+		}
+		config.prefer_prefix = self.prefer_prefix;
+		config
+	}
 }
 
 impl SearchUi {
@@ -94,9 +213,23 @@ impl SearchUi {
 			data,
 			ui_config: UiConfig,
 			input_title: None,
+			icons: IconStyle::NerdFont,
+			persist_query: false,
+			frame_interval: None,
+			match_details: false,
+			window_title: false,
+			label_width: DEFAULT_LABEL_WIDTH,
+			matching: MatchOptions::default(),
+			multi_select: false,
+			marked: Vec::new(),
 		}
 	}
 
+	/// Shorthand for `SearchUi::new(SearchData::from_paths(paths))`.
+	pub fn from_paths(paths: impl IntoIterator<Item = impl Into<String>>) -> Self {
+		Self::new(SearchData::from_paths(paths))
+	}
+
 	pub fn with_ui_config(mut self, ui_config: UiConfig) -> Self {
 		self.ui_config = ui_config;
 		self
@@ -112,122 +245,1305 @@ impl SearchUi {
 		self
 	}
 
-	pub fn run(self) -> Result<SearchOutcome> {
-		let mut options = PickerOptions::new();
+	/// Prefix entries with Nerd Font icons (default), or nothing. See [`Self::with_icon_style`].
+	pub fn with_icons(mut self, icons: bool) -> Self {
+		self.icons = if icons {
+			IconStyle::NerdFont
+		} else {
+			IconStyle::None
+		};
+		self
+	}
+
+	/// Choose the entry prefix. [`IconStyle::NerdFont`] falls back to [`IconStyle::Ascii`] when
+	/// `NO_COLOR` or `GIT_SPARTA_NO_ICONS` is set, or `TERM` is `dumb` or `linux`.
+	pub fn with_icon_style(mut self, icons: IconStyle) -> Self {
+		self.icons = icons;
+		self
+	}
+
+	/// Remember the last query per context label (see [`SearchData::with_context`]) and use it as
+	/// the initial query next time, unless one is set explicitly. Off by default.
+	pub fn with_query_persistence(mut self, persist: bool) -> Self {
+		self.persist_query = persist;
+		self
+	}
+
+	/// How often the picker wakes to process input and redraw (nucleo-picker defaults to 15ms).
+	///
+	/// The picker only redraws parts of the screen that changed, but it still polls once per
+	/// interval; a longer interval lowers idle CPU use at the cost of input latency.
+	pub fn with_frame_interval(mut self, interval: Duration) -> Self {
+		self.frame_interval = Some(interval);
+		self
+	}
+
+	/// Report the accepted entry's match score and matched character indices in
+	/// [`SearchOutcome::match_details`]. Off by default.
+	pub fn with_match_details(mut self, match_details: bool) -> Self {
+		self.match_details = match_details;
+		self
+	}
+
+	/// Show the input title and context label (see [`SearchData::with_context`]) in the
+	/// terminal window title while the picker is open, restoring the previous title afterwards.
+	/// Off by default; nothing is written unless stderr is a terminal.
+	pub fn with_window_title(mut self, window_title: bool) -> Self {
+		self.window_title = window_title;
+		self
+	}
+
+	/// Maximum display width of the context label in the window title (see
+	/// [`Self::with_window_title`]). Defaults to [`DEFAULT_LABEL_WIDTH`].
+	pub fn with_label_width(mut self, width: usize) -> Self {
+		self.label_width = width;
+		self
+	}
+
+	/// How query case is compared: [`CaseMatching::Smart`] (default) ignores case unless the
+	/// query contains an uppercase letter.
+	pub fn with_case_matching(mut self, case_matching: CaseMatching) -> Self {
+		self.matching.case_matching = case_matching;
+		self
+	}
+
+	/// Whether accented latin letters match their ASCII base: [`Normalization::Smart`]
+	/// (default) does so unless the query itself contains such letters.
+	pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+		self.matching.normalization = normalization;
+		self
+	}
+
+	/// Give matches a small bonus the closer they start to the beginning of the entry
+	/// (nucleo's `prefer_prefix`). Off by default.
+	pub fn with_prefer_prefix(mut self, prefer_prefix: bool) -> Self {
+		self.matching.prefer_prefix = prefer_prefix;
+		self
+	}
+
+	/// Score word boundaries the way nucleo does for file paths, where only `/` and `:`
+	/// start a new word. Off by default.
+	pub fn with_path_matching(mut self, path_matching: bool) -> Self {
+		self.matching.path_matching = path_matching;
+		self
+	}
+
+	/// Allow several entries to be accepted at once: `Tab` marks or unmarks the highlighted
+	/// entry and `Enter` accepts the marked ones, reported in [`SearchOutcome::selections`].
+	/// Off by default.
+	pub fn with_multi_select(mut self, multi_select: bool) -> Self {
+		self.multi_select = multi_select;
+		self
+	}
+
+	/// Entries already marked when a multi-select picker opens, e.g. the current choice.
+	/// Selections that match no entry are ignored.
+	pub fn with_marked(mut self, marked: impl IntoIterator<Item = SearchSelection>) -> Self {
+		self.marked = marked.into_iter().collect();
+		self
+	}
+
+	pub fn run(mut self) -> Result<SearchOutcome> {
+		let history = self
+			.persist_query
+			.then(|| self.data.context.clone().zip(query_history_path()))
+			.flatten();
+		if let Some((context, path)) = &history
+			&& self.data.initial_query.is_none()
+		{
+			self.data.initial_query = load_query(path, context);
+		}
+
+		let mut options = PickerOptions::new()
+			.config(self.matching.config())
+			.case_matching(self.matching.case_matching)
+			.normalization(self.matching.normalization);
 		if let Some(query) = &self.data.initial_query {
 			options = options.query(query.clone());
 		}
+		if let Some(interval) = self.frame_interval {
+			options = options.frame_interval(interval);
+		}
 
-		let mut picker = options.picker(EntryRenderer);
+		let icons = self.icon_style();
+		// nucleo-picker has no header line, so the title and context can only go in the window
+		// title.
+		let _title = self
+			.window_title
+			.then(|| {
+				header_label(
+					self.input_title.as_deref(),
+					self.data.context.as_deref(),
+					self.label_width,
+				)
+			})
+			.flatten()
+			.and_then(|label| TerminalTitle::set(&label));
+		let mut entries = build_entries(
+			self.data,
+			&self.ui_config,
+			self.input_title.as_deref(),
+			icons,
+		);
+		let marks = self.multi_select.then(|| {
+			add_mark_column(&mut entries);
+			Marks::new(entries.len())
+		});
+		let mut picker = options.picker(EntryRenderer {
+			marks: marks.clone(),
+		});
 
-		let injector = nucleo_picker::Picker::injector(&picker);
-		for entry in build_entries(self.data, &self.ui_config, self.input_title.as_deref()) {
-			injector.push(entry);
+		let mut outcome = run_picker(&mut picker, &entries, marks.as_deref(), &self.marked)?;
+		if self.match_details
+			&& let Some(selection) = &outcome.selection
+			&& let Some(entry) = entries.iter().find(|entry| entry.selection == *selection)
+		{
+			outcome.match_details = match_details(&outcome.query, &entry.render, &self.matching);
+		}
+		if let Some((context, path)) = &history {
+			save_query(path, context, &outcome.query);
 		}
+		Ok(outcome)
+	}
+}
 
-		let pick_result = nucleo_picker::Picker::pick(&mut picker);
+impl SearchUi {
+	/// Rank the entries against `query` in the order the picker would list them, without
+	/// opening the terminal UI.
+	///
+	/// Each selection comes with its nucleo score (0 for an empty query); entries that don't
+	/// match are left out.
+	pub fn rank(&self, query: &str) -> Vec<(SearchSelection, u32)> {
+		let mut entries = build_entries(
+			self.data.clone(),
+			&self.ui_config,
+			self.input_title.as_deref(),
+			self.icon_style(),
+		);
+		if self.multi_select {
+			add_mark_column(&mut entries);
+		}
+		rank_entries(entries, query, &self.matching)
+	}
+
+	/// The entry prefix to render, after the environment fallback.
+	fn icon_style(&self) -> IconStyle {
+		match self.icons {
+			IconStyle::NerdFont if icons_unsupported_by_env() => IconStyle::Ascii,
+			icons => icons,
+		}
+	}
+}
+
+/// Score and sort `entries` like nucleo: best score first, then the shorter line, then the
+/// original order. An empty query keeps every entry in its original order.
+fn rank_entries(
+	entries: Vec<PickerEntry>,
+	query: &str,
+	options: &MatchOptions,
+) -> Vec<(SearchSelection, u32)> {
+	let pattern = Pattern::parse(query, options.case_matching, options.normalization);
+	let mut matcher = Matcher::new(options.config());
+	let mut buf = Vec::new();
+	let mut ranked: Vec<(SearchSelection, u32, usize)> = entries
+		.into_iter()
+		.filter_map(|entry| {
+			let haystack = Utf32Str::new(&entry.render, &mut buf);
+			let len = haystack.len();
+			let score = pattern.score(haystack, &mut matcher)?;
+			Some((entry.selection, score, len))
+		})
+		.collect();
+	if !pattern.atoms.is_empty() {
+		// Stable, so equal entries keep their original order.
+		ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)));
+	}
+	ranked
+		.into_iter()
+		.map(|(selection, score, _)| (selection, score))
+		.collect()
+}
+
+/// Score `haystack` against `query` the way the picker does.
+fn match_details(query: &str, haystack: &str, options: &MatchOptions) -> Option<MatchDetails> {
+	let pattern = Pattern::parse(query, options.case_matching, options.normalization);
+	let mut matcher = Matcher::new(options.config());
+	let mut buf = Vec::new();
+	let mut indices = Vec::new();
+	let haystack_utf32 = Utf32Str::new(haystack, &mut buf);
+	let score = pattern.indices(haystack_utf32, &mut matcher, &mut indices)?;
+
+	// nucleo indexes grapheme clusters, except that text whose clusters all start with an
+	// ASCII char (e.g. `e` + combining accent) is matched byte-wise. Map those byte offsets
+	// back to clusters so indices always count glyphs.
+	if matches!(haystack_utf32, Utf32Str::Ascii(_)) && !haystack.is_ascii() {
+		let starts: Vec<usize> = haystack.grapheme_indices(true).map(|(i, _)| i).collect();
+		for index in &mut indices {
+			*index = (starts.partition_point(|&start| start <= *index as usize) - 1) as u32;
+		}
+	}
+
+	indices.sort_unstable();
+	indices.dedup();
+	Some(MatchDetails { score, indices })
+}
+
+/// State file holding the last query per context label.
+fn query_history_path() -> Option<PathBuf> {
+	ProjectDirs::from("", "", "git-sparta").map(|dirs| dirs.cache_dir().join("queries.json"))
+}
+
+/// Read the stored query for `context`; a missing or unreadable file yields `None`.
+fn load_query(path: &Path, context: &str) -> Option<String> {
+	let contents = fs::read_to_string(path).ok()?;
+	let queries: BTreeMap<String, String> = serde_json::from_str(&contents).ok()?;
+	queries.get(context).cloned()
+}
+
+/// Store `query` for `context`. Failures are ignored: persistence is a convenience only.
+fn save_query(path: &Path, context: &str, query: &str) {
+	let mut queries: BTreeMap<String, String> = fs::read_to_string(path)
+		.ok()
+		.and_then(|contents| serde_json::from_str(&contents).ok())
+		.unwrap_or_default();
+	queries.insert(context.to_owned(), query.to_owned());
+
+	if let Some(parent) = path.parent() {
+		let _ = fs::create_dir_all(parent);
+	}
+	if let Ok(contents) = serde_json::to_string_pretty(&queries) {
+		let _ = fs::write(path, contents);
+	}
+}
+
+/// Shorten `path` for display within `max_width` columns: the home directory becomes `~`
+/// and anything still too long is truncated in the middle, keeping both ends.
+pub fn display_path(path: &str, max_width: usize) -> String {
+	let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+	shorten_path(path, home.as_deref(), max_width)
+}
+
+fn shorten_path(path: &str, home: Option<&Path>, max_width: usize) -> String {
+	let relative = home
+		.filter(|home| !home.as_os_str().is_empty())
+		.and_then(|home| Path::new(path).strip_prefix(home).ok());
+	let path = match relative {
+		Some(rest) if rest.as_os_str().is_empty() => "~".to_owned(),
+		Some(rest) => format!("~/{}", rest.display()),
+		None => path.to_owned(),
+	};
+	truncate_middle(&path, max_width)
+}
+
+/// Replace the middle of `text` with `…` so it fits in `max_width` display columns.
+fn truncate_middle(text: &str, max_width: usize) -> String {
+	const ELLIPSIS: &str = "…";
+	if text.width() <= max_width {
+		return text.to_owned();
+	}
+	if max_width == 0 {
+		return String::new();
+	}
+
+	// Favour the end of the path, which holds the most specific components.
+	let available = max_width - ELLIPSIS.width();
+	fn take<'a>(graphemes: impl Iterator<Item = &'a str>, budget: usize) -> Vec<&'a str> {
+		let mut used = 0;
+		graphemes
+			.take_while(|grapheme| {
+				used += grapheme.width();
+				used <= budget
+			})
+			.collect()
+	}
+	let head = take(text.graphemes(true), available / 2);
+	let mut tail = take(text.graphemes(true).rev(), available - available / 2);
+	tail.reverse();
+	format!("{}{ELLIPSIS}{}", head.concat(), tail.concat())
+}
+
+/// `title — context` for the window title, with the context shortened by [`display_path`].
+fn header_label(title: Option<&str>, context: Option<&str>, max_width: usize) -> Option<String> {
+	let context = context.map(|context| display_path(context, max_width));
+	match (title, context) {
+		(Some(title), Some(context)) => Some(format!("{title} — {context}")),
+		(Some(title), None) => Some(title.to_owned()),
+		(None, context) => context,
+	}
+}
 
-		let outcome = match pick_result {
-			Ok(opt) => {
-				let selection = opt.map(|entry| entry.selection.clone());
-				let query = nucleo_picker::Picker::query(&picker).to_string();
-				SearchOutcome {
-					accepted: selection.is_some(),
-					query,
-					selection,
+/// Sets the terminal window title and restores the previous one when dropped.
+///
+/// Uses the xterm title stack; terminals without it keep the new title.
+struct TerminalTitle;
+
+impl TerminalTitle {
+	fn set(title: &str) -> Option<Self> {
+		let mut stderr = io::stderr();
+		if !stderr.is_terminal() {
+			return None;
+		}
+		let title: String = title.chars().filter(|c| !c.is_control()).collect();
+		write!(stderr, "\x1b[22;2t\x1b]2;{title}\x07")
+			.and_then(|()| stderr.flush())
+			.ok()
+			.map(|()| Self)
+	}
+}
+
+impl Drop for TerminalTitle {
+	fn drop(&mut self) {
+		let mut stderr = io::stderr();
+		let _ = write!(stderr, "\x1b[23;2t").and_then(|()| stderr.flush());
+	}
+}
+
+/// Run the picker until an entry or the query is accepted, or the user cancels.
+///
+/// With `marks`, each `Tab` closes the picker on the highlighted entry, toggles its mark and
+/// reopens the same picker, so the query, the items and the cursor carry over. `preselected`
+/// entries start out marked.
+fn run_picker(
+	picker: &mut nucleo_picker::Picker<PickerEntry, EntryRenderer>,
+	entries: &[PickerEntry],
+	marks: Option<&Marks>,
+	preselected: &[SearchSelection],
+) -> Result<SearchOutcome> {
+	// Items are rendered for matching as they are pushed, so push them before marking any.
+	picker.extend(entries.iter().cloned());
+	let mut marked: Vec<usize> = Vec::new();
+	if let Some(marks) = marks {
+		for entry in entries
+			.iter()
+			.filter(|entry| preselected.contains(&entry.selection))
+		{
+			marks.toggle(entry.index);
+			marked.push(entry.index);
+		}
+	}
+
+	let stderr = io::stderr().lock();
+	if !stderr.is_terminal() {
+		return Err(not_interactive());
+	}
+	let exit = Cell::new(Exit::Accept);
+	let mut writer = ScreenWriter::new(BufWriter::new(stderr), &exit);
+	let picked = loop {
+		let pick_result = picker.pick_with_io(
+			StdinReader::new(|key_event: KeyEvent| {
+				if key_event.kind == KeyEventKind::Press {
+					exit.set(match (key_event.modifiers, key_event.code) {
+						(KeyModifiers::ALT, KeyCode::Enter) => Exit::Query,
+						(KeyModifiers::NONE, KeyCode::Tab) if marks.is_some() => Exit::Mark,
+						_ => Exit::Accept,
+					});
+					match exit.get() {
+						Exit::Query => return Some(Event::Quit),
+						Exit::Mark => return Some(Event::Select),
+						Exit::Accept => {}
+					}
 				}
+				keybind(key_event)
+			}),
+			&mut writer,
+		);
+
+		let picked = match pick_result {
+			Ok(opt) => opt.map(|entry| entry.index),
+			Err(err) => {
+				writer.leave_alternate_screen()?;
+				check_pick_error(err)?;
+				None
 			}
-			Err(PickError::UserInterrupted) => {
-				let query = nucleo_picker::Picker::query(&picker).to_string();
-				SearchOutcome {
-					accepted: false,
-					query,
-					selection: None,
+		};
+		match (picked, marks) {
+			(Some(index), Some(marks)) if exit.get() == Exit::Mark => {
+				if marks.toggle(index) {
+					marked.push(index);
+				} else {
+					marked.retain(|&marked| marked != index);
 				}
 			}
-			Err(PickError::NotInteractive) => {
-				return Err(anyhow!(
-					"interactive picker requires an interactive stderr; rerun in a terminal or pass --yes"
-				));
+			_ => break picked,
+		}
+	};
+	writer.leave_alternate_screen()?;
+	let query = picker.query().to_string();
+
+	let status = match (picked, exit.get()) {
+		(Some(_), _) => PickStatus::Accepted,
+		(None, Exit::Query) => PickStatus::Empty,
+		(None, _) => PickStatus::Aborted,
+	};
+	let selections = match picked {
+		Some(index) if marked.is_empty() => vec![entries[index].selection.clone()],
+		Some(_) => marked
+			.iter()
+			.map(|&index| entries[index].selection.clone())
+			.collect(),
+		None => Vec::new(),
+	};
+	Ok(SearchOutcome::new(status, query, selections))
+}
+
+/// Why the picker last closed, as decided by the key that closed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Exit {
+	/// `Enter` (or a cancel key, which picks nothing).
+	Accept,
+	/// `Tab` in multi-select mode: toggle the highlighted entry's mark and reopen.
+	Mark,
+	/// `Alt+Enter`: accept the query.
+	Query,
+}
+
+/// Passes the picker's output through, except that it stays on the alternate screen while
+/// the picker is closed only to toggle a mark, so reopening it doesn't flicker.
+///
+/// nucleo-picker enters and leaves the alternate screen on every pick; on terminals driven
+/// through the Windows console API instead of escape codes the flicker remains.
+struct ScreenWriter<'a, W: Write> {
+	inner: W,
+	exit: &'a Cell<Exit>,
+	alternate: bool,
+}
+
+impl<'a, W: Write> ScreenWriter<'a, W> {
+	/// crossterm's `EnterAlternateScreen` and `LeaveAlternateScreen`, each written in one go.
+	const ENTER: &'static [u8] = b"\x1b[?1049h";
+	const LEAVE: &'static [u8] = b"\x1b[?1049l";
+
+	fn new(inner: W, exit: &'a Cell<Exit>) -> Self {
+		Self {
+			inner,
+			exit,
+			alternate: false,
+		}
+	}
+
+	/// Leave the alternate screen if a pick that ended in [`Exit::Mark`] left us on it.
+	fn leave_alternate_screen(&mut self) -> io::Result<()> {
+		if self.alternate {
+			self.alternate = false;
+			self.inner.write_all(Self::LEAVE)?;
+		}
+		self.inner.flush()
+	}
+}
+
+impl<W: Write> Write for ScreenWriter<'_, W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if buf == Self::ENTER {
+			if self.alternate {
+				return Ok(buf.len());
 			}
-			Err(PickError::Disconnected) => {
-				return Err(anyhow!("picker event channel disconnected"));
+			self.alternate = true;
+		} else if buf == Self::LEAVE {
+			if self.exit.get() == Exit::Mark {
+				return Ok(buf.len());
 			}
-			Err(PickError::IO(err)) => return Err(err.into()),
-			Err(_) => unreachable!("application never provides abort errors to the picker"),
-		};
+			self.alternate = false;
+		}
+		self.inner.write(buf)
+	}
 
-		Ok(outcome)
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+fn not_interactive() -> anyhow::Error {
+	anyhow!("interactive picker requires an interactive stderr; rerun in a terminal or pass --yes")
+}
+
+/// Map picker errors to either a cancelled outcome (`Ok`) or a hard failure.
+fn check_pick_error(err: PickError) -> Result<()> {
+	match err {
+		PickError::UserInterrupted => Ok(()),
+		PickError::NotInteractive => Err(not_interactive()),
+		PickError::Disconnected => Err(anyhow!("picker event channel disconnected")),
+		PickError::IO(err) => Err(err.into()),
+		_ => unreachable!("application never provides abort errors to the picker"),
 	}
 }
 
+/// How the picker was closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickStatus {
+	/// An entry was chosen with `Enter`.
+	Accepted,
+	/// The typed query was accepted with `Alt+Enter` without choosing an entry.
+	Empty,
+	/// The user cancelled (`Esc`, `Ctrl+C`, ...).
+	Aborted,
+}
+
+/// How the accepted entry matched the final query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchDetails {
+	/// nucleo match score (higher is better; 0 for an empty query).
+	pub score: u32,
+	/// Sorted indices of the matched grapheme clusters (glyphs) in the rendered entry line.
+	pub indices: Vec<u32>,
+}
+
 pub struct SearchOutcome {
+	pub status: PickStatus,
+	/// `true` unless the picker was aborted.
 	pub accepted: bool,
 	pub query: String,
+	/// The accepted entry; the first of [`Self::selections`] in multi-select mode.
 	pub selection: Option<SearchSelection>,
+	/// Every accepted entry: the marked ones in the order they were marked (preselected ones
+	/// first), or just the accepted entry when none is marked. Empty unless accepted.
+	pub selections: Vec<SearchSelection>,
+	/// Match score and indices of `selection`, if requested with
+	/// [`SearchUi::with_match_details`].
+	pub match_details: Option<MatchDetails>,
+}
+
+impl SearchOutcome {
+	fn new(status: PickStatus, query: String, selections: Vec<SearchSelection>) -> Self {
+		Self {
+			status,
+			accepted: status != PickStatus::Aborted,
+			query,
+			selection: selections.first().cloned(),
+			selections,
+			match_details: None,
+		}
+	}
+}
+
+/// Keybindings for the picker: the nucleo defaults plus page and first/last navigation, and
+/// readline's `Ctrl+k`.
+///
+/// `Home`/`End` move the match list instead of the query cursor; nucleo's `Ctrl+a`/`Ctrl+e`
+/// still jump to the start/end of the query.
+fn keybind(key_event: KeyEvent) -> Option<Event> {
+	if key_event.kind != KeyEventKind::Press {
+		return keybind_default(key_event);
+	}
+
+	match (key_event.modifiers, key_event.code) {
+		(KeyModifiers::NONE, KeyCode::PageUp) => {
+			Some(Event::MatchList(MatchListEvent::Up(page_size())))
+		}
+		(KeyModifiers::NONE, KeyCode::PageDown) => {
+			Some(Event::MatchList(MatchListEvent::Down(page_size())))
+		}
+		(KeyModifiers::NONE, KeyCode::Home) => Some(Event::MatchList(MatchListEvent::Reset)),
+		// The match list grows upwards from the prompt, so the last match is "up".
+		(KeyModifiers::NONE, KeyCode::End) => {
+			Some(Event::MatchList(MatchListEvent::Up(usize::MAX)))
+		}
+		// nucleo moves up on `Ctrl+k`; `Ctrl+p` still does that.
+		(KeyModifiers::CONTROL, KeyCode::Char('k')) => Some(Event::Prompt(PromptEvent::ClearAfter)),
+		_ => keybind_default(key_event),
+	}
+}
+
+/// Number of match rows visible on screen (the prompt takes one line).
+fn page_size() -> usize {
+	crossterm::terminal::size()
+		.map(|(_, height)| usize::from(height.saturating_sub(1)))
+		.unwrap_or(1)
+		.max(1)
 }
 
 #[derive(Clone, Debug)]
 struct PickerEntry {
+	/// Position in the list [`build_entries`] returned.
+	index: usize,
 	render: String,
 	selection: SearchSelection,
 }
 
+/// Default maximum display width of the context label; see [`SearchUi::with_label_width`].
+pub const DEFAULT_LABEL_WIDTH: usize = 60;
+
 const ATTRIBUTE_ICON: char = '󰊢';
 const GENERIC_FILE_ICON: &str = "󰈔";
 
-fn build_entries(data: SearchData, _config: &UiConfig, _title: Option<&str>) -> Vec<PickerEntry> {
+/// Prefix shown before each entry to tell tags from files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IconStyle {
+	/// Nerd Font glyphs, with a per-file-type icon (default).
+	#[default]
+	NerdFont,
+	/// Plain `[tag]` / `[file]` markers for terminals without Nerd Fonts.
+	Ascii,
+	/// No prefix.
+	None,
+}
+
+fn build_entries(
+	mut data: SearchData,
+	_config: &UiConfig,
+	_title: Option<&str>,
+	icons: IconStyle,
+) -> Vec<PickerEntry> {
 	fn assert_send_sync_static<T: Send + Sync + 'static>() {}
 	assert_send_sync_static::<PickerEntry>();
 
-	let mut entries = Vec::new();
-
-	if !data.attributes.is_empty() {
-		for attribute in data.attributes.into_iter() {
-			let render = format!(
-				"{ATTRIBUTE_ICON} {name}  ({count} matches)",
-				name = attribute.name,
-				count = attribute.count
-			);
-			entries.push(PickerEntry {
-				render,
-				selection: SearchSelection::Attribute(attribute),
-			});
-		}
+	match data.sort {
+		SortBy::Name => data.attributes.sort_by(|a, b| a.name.cmp(&b.name)),
+		SortBy::CountDesc => data
+			.attributes
+			.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name))),
+	}
+	if data.sort_files {
+		data.files.sort_by(|a, b| a.path.cmp(&b.path));
 	}
 
-	for file in data.files.into_iter() {
-		let icon = FileIcon::from(file.path.as_str());
-		let icon_string = icon.to_string();
-		let icon = if icon_string == "*" {
-			GENERIC_FILE_ICON
-		} else {
-			icon_string.as_str()
-		};
-		let mut render = format!("{icon} {}", file.path);
-		if !file.tags.is_empty() {
-			render.push_str("  [");
-			render.push_str(&file.tags.join(", "));
-			render.push(']');
-		}
-		entries.push(PickerEntry {
+	let attribute_columns = data
+		.attributes
+		.iter()
+		.map(|attribute| {
+			let icon = match icons {
+				IconStyle::NerdFont => format!("{ATTRIBUTE_ICON} "),
+				IconStyle::Ascii => "[tag] ".to_owned(),
+				IconStyle::None => String::new(),
+			};
+			(
+				format!("{icon}{}", attribute.name),
+				Some(format!("({} matches)", attribute.count)),
+			)
+		})
+		.collect();
+	let file_columns = data
+		.files
+		.iter()
+		.map(|file| {
+			let icon = match icons {
+				IconStyle::NerdFont => format!("{} ", file_icon(&file.path)),
+				IconStyle::Ascii => "[file] ".to_owned(),
+				IconStyle::None => String::new(),
+			};
+			let tags = (!file.tags.is_empty()).then(|| format!("[{}]", file.tags.join(", ")));
+			(format!("{icon}{}", file.path), tags)
+		})
+		.collect();
+
+	let attributes: Vec<PickerEntry> = data
+		.attributes
+		.into_iter()
+		.zip(align_columns(attribute_columns))
+		.map(|(attribute, render)| PickerEntry {
+			index: 0,
+			render,
+			selection: SearchSelection::Attribute(attribute),
+		})
+		.collect();
+	let files: Vec<PickerEntry> = data
+		.files
+		.into_iter()
+		.zip(align_columns(file_columns))
+		.map(|(file, render)| PickerEntry {
+			index: 0,
 			render,
 			selection: SearchSelection::File(file),
-		});
-	}
+		})
+		.collect();
 
+	let entries = if data.files_first {
+		files.into_iter().chain(attributes)
+	} else {
+		attributes.into_iter().chain(files)
+	};
 	entries
+		.enumerate()
+		.map(|(index, entry)| PickerEntry { index, ..entry })
+		.collect()
 }
 
-struct EntryRenderer;
+const UNMARKED: &str = "○ ";
+const MARKED: &str = "● ";
+
+/// Prefix every entry with the multi-select mark column, unmarked.
+///
+/// The column is non-ASCII on purpose: nucleo-picker draws ASCII items straight from the
+/// text it matched, but renders other items again when drawing them, which is how a marked
+/// entry gets its `●` without the matched text changing.
+fn add_mark_column(entries: &mut [PickerEntry]) {
+	for entry in entries {
+		entry.render.insert_str(0, UNMARKED);
+	}
+}
+
+/// Mark state of each entry (by [`PickerEntry::index`]) in a multi-select picker.
+struct Marks(Vec<AtomicBool>);
+
+impl Marks {
+	fn new(len: usize) -> Arc<Self> {
+		Arc::new(Self((0..len).map(|_| AtomicBool::new(false)).collect()))
+	}
+
+	fn is_marked(&self, index: usize) -> bool {
+		self.0[index].load(Ordering::Relaxed)
+	}
+
+	/// Flip the mark on entry `index`, returning whether it is now marked.
+	fn toggle(&self, index: usize) -> bool {
+		!self.0[index].fetch_xor(true, Ordering::Relaxed)
+	}
+}
+
+/// Join `(left, right)` cells so every right-hand cell starts at the same display column.
+fn align_columns(rows: Vec<(String, Option<String>)>) -> Vec<String> {
+	let width = rows
+		.iter()
+		.filter(|(_, right)| right.is_some())
+		.map(|(left, _)| left.width())
+		.max()
+		.unwrap_or(0);
+
+	rows.into_iter()
+		.map(|(left, right)| match right {
+			Some(right) => {
+				let padding = width - left.width();
+				format!("{left}{:padding$}  {right}", "")
+			}
+			None => left,
+		})
+		.collect()
+}
+
+fn file_icon(path: &str) -> String {
+	let icon = FileIcon::from(path).to_string();
+	if icon == "*" {
+		GENERIC_FILE_ICON.to_owned()
+	} else {
+		icon
+	}
+}
+
+/// Nerd Font glyphs are replaced when `NO_COLOR` or `GIT_SPARTA_NO_ICONS` is set to a
+/// non-empty value, or when `TERM` is `dumb` or `linux` (the kernel console).
+///
+/// An unset `TERM` says nothing: Windows terminals normally don't set it.
+fn icons_unsupported_by_env() -> bool {
+	let set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+	set("NO_COLOR")
+		|| set("GIT_SPARTA_NO_ICONS")
+		|| std::env::var_os("TERM").is_some_and(|term| term_lacks_icons(&term))
+}
+
+fn term_lacks_icons(term: &std::ffi::OsStr) -> bool {
+	term == "dumb" || term == "linux"
+}
+
+#[derive(Default)]
+struct EntryRenderer {
+	/// Set in multi-select mode, where entries carry the mark column.
+	marks: Option<Arc<Marks>>,
+}
 
 impl Render<PickerEntry> for EntryRenderer {
 	type Str<'a>
-		= &'a str
+		= Cow<'a, str>
 	where
 		PickerEntry: 'a;
 
 	fn render<'a>(&self, item: &'a PickerEntry) -> Self::Str<'a> {
-		item.render.as_str()
+		match &self.marks {
+			Some(marks) if marks.is_marked(item.index) => {
+				Cow::Owned(item.render.replacen(UNMARKED, MARKED, 1))
+			}
+			_ => Cow::Borrowed(&item.render),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Lines the picker would show for `query`, rendered by [`EntryRenderer`].
+	fn matching(query: &str, data: SearchData) -> Vec<String> {
+		let renders: Vec<String> = build_entries(data, &UiConfig, None, IconStyle::NerdFont)
+			.iter()
+			.map(|entry| EntryRenderer::default().render(entry).into_owned())
+			.collect();
+		let pattern = Pattern::parse(query, CaseMatching::Smart, Normalization::Smart);
+		let mut matcher = Matcher::default();
+		let mut found: Vec<String> = pattern
+			.match_list(renders, &mut matcher)
+			.into_iter()
+			.map(|(render, _)| render)
+			.collect();
+		found.sort();
+		found
+	}
+
+	fn attributes(names: &[&str]) -> SearchData {
+		SearchData::new().with_attributes(
+			names
+				.iter()
+				.map(|name| AttributeRow::new(*name, 1))
+				.collect(),
+		)
+	}
+
+	fn press(code: KeyCode) -> KeyEvent {
+		KeyEvent::new(code, KeyModifiers::NONE)
+	}
+
+	#[test]
+	fn test_home_end_select_first_and_last() {
+		assert!(matches!(
+			keybind(press(KeyCode::Home)),
+			Some(Event::MatchList(MatchListEvent::Reset))
+		));
+		assert!(matches!(
+			keybind(press(KeyCode::End)),
+			Some(Event::MatchList(MatchListEvent::Up(usize::MAX)))
+		));
+	}
+
+	#[test]
+	fn test_page_keys_move_by_at_least_one_row() {
+		assert!(matches!(
+			keybind(press(KeyCode::PageUp)),
+			Some(Event::MatchList(MatchListEvent::Up(n))) if n >= 1
+		));
+		assert!(matches!(
+			keybind(press(KeyCode::PageDown)),
+			Some(Event::MatchList(MatchListEvent::Down(n))) if n >= 1
+		));
+	}
+
+	#[test]
+	fn test_other_keys_fall_back_to_defaults() {
+		assert!(matches!(
+			keybind(press(KeyCode::Enter)),
+			Some(Event::Select)
+		));
+		assert!(matches!(
+			keybind(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+			Some(Event::UserInterrupt)
+		));
+	}
+
+	#[test]
+	fn test_line_editing_keys_edit_the_query() {
+		let ctrl = |c| keybind(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+		assert!(matches!(
+			ctrl('u'),
+			Some(Event::Prompt(PromptEvent::ClearBefore))
+		));
+		assert!(matches!(
+			ctrl('w'),
+			Some(Event::Prompt(PromptEvent::BackspaceWord(1)))
+		));
+		assert!(matches!(
+			ctrl('k'),
+			Some(Event::Prompt(PromptEvent::ClearAfter))
+		));
+		assert!(matches!(
+			ctrl('p'),
+			Some(Event::MatchList(MatchListEvent::Up(1)))
+		));
+		// The query cursor moves with `Ctrl+a`/`Ctrl+e`, since `Home`/`End` select matches.
+		assert!(matches!(
+			ctrl('a'),
+			Some(Event::Prompt(PromptEvent::ToStart))
+		));
+		assert!(matches!(ctrl('e'), Some(Event::Prompt(PromptEvent::ToEnd))));
+	}
+
+	fn attribute_names(data: SearchData) -> Vec<String> {
+		build_entries(data, &UiConfig, None, IconStyle::NerdFont)
+			.into_iter()
+			.filter_map(|entry| match entry.selection {
+				SearchSelection::Attribute(attribute) => Some(attribute.name),
+				SearchSelection::File(_) => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_entries_without_icons_start_with_name() {
+		let data = SearchData::new()
+			.with_attributes(vec![AttributeRow::new("docs", 4)])
+			.with_files(vec![FileRow::new("src/lib.rs", ["docs"])]);
+		let renders: Vec<String> = build_entries(data, &UiConfig, None, IconStyle::None)
+			.into_iter()
+			.map(|entry| entry.render)
+			.collect();
+		assert_eq!(renders, ["docs  (4 matches)", "src/lib.rs  [docs]"]);
+	}
+
+	#[test]
+	fn test_ascii_icons_mark_tags_and_files() {
+		let data = SearchData::new()
+			.with_attributes(vec![AttributeRow::new("docs", 4)])
+			.with_files(vec![FileRow::new("src/lib.rs", ["docs"])]);
+		let renders: Vec<String> = build_entries(data, &UiConfig, None, IconStyle::Ascii)
+			.into_iter()
+			.map(|entry| entry.render)
+			.collect();
+		assert_eq!(
+			renders,
+			["[tag] docs  (4 matches)", "[file] src/lib.rs  [docs]"]
+		);
+	}
+
+	#[test]
+	fn test_only_known_plain_terminals_lack_icons() {
+		assert!(term_lacks_icons("dumb".as_ref()));
+		assert!(term_lacks_icons("linux".as_ref()));
+		assert!(!term_lacks_icons("xterm-256color".as_ref()));
+	}
+
+	#[test]
+	fn test_entries_are_column_aligned() {
+		let data = SearchData::new()
+			.with_attributes(vec![
+				AttributeRow::new("docs", 4),
+				AttributeRow::new("backend", 11),
+			])
+			.with_files(vec![
+				FileRow::new("api/routes.rs", ["api"]),
+				FileRow::new("db/schema.sql", Vec::<String>::new()),
+				FileRow::new("docs/api.md", ["docs", "api"]),
+			]);
+		let renders: Vec<String> = build_entries(data, &UiConfig, None, IconStyle::None)
+			.into_iter()
+			.map(|entry| entry.render)
+			.collect();
+		assert_eq!(
+			renders,
+			[
+				"backend  (11 matches)",
+				"docs     (4 matches)",
+				"api/routes.rs  [api]",
+				"db/schema.sql",
+				"docs/api.md    [docs, api]",
+			]
+		);
+	}
+
+	#[test]
+	fn test_align_columns_uses_display_width() {
+		let rows = vec![
+			("日本".to_owned(), Some("x".to_owned())),
+			("abcd".to_owned(), Some("y".to_owned())),
+			("abcde".to_owned(), Some("z".to_owned())),
+		];
+		assert_eq!(align_columns(rows), ["日本   x", "abcd   y", "abcde  z"]);
+	}
+
+	#[test]
+	fn test_sort_attributes_by_name_or_count() {
+		let data = || {
+			SearchData::new().with_attributes(vec![
+				AttributeRow::new("docs", 4),
+				AttributeRow::new("backend", 11),
+				AttributeRow::new("api", 4),
+			])
+		};
+		assert_eq!(attribute_names(data()), ["api", "backend", "docs"]);
+		assert_eq!(
+			attribute_names(data().with_sort(SortBy::CountDesc)),
+			["backend", "api", "docs"]
+		);
+	}
+
+	#[test]
+	fn test_files_keep_their_order_unless_sorted() {
+		let data = || SearchData::from_paths(["b.txt", "a.txt"]);
+		let paths = |data| {
+			build_entries(data, &UiConfig, None, IconStyle::None)
+				.into_iter()
+				.map(|entry| match entry.selection {
+					SearchSelection::File(file) => file.path,
+					SearchSelection::Attribute(attribute) => attribute.name,
+				})
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(data()), ["b.txt", "a.txt"]);
+		assert_eq!(paths(data().with_sorted_files(true)), ["a.txt", "b.txt"]);
+	}
+
+	#[test]
+	fn test_files_first_reorders_entries() {
+		let data = || {
+			SearchData::new()
+				.with_attributes(vec![AttributeRow::new("alpha", 1)])
+				.with_files(vec![FileRow::new("a/one.txt", ["alpha"])])
+		};
+		let kinds = |data| {
+			build_entries(data, &UiConfig, None, IconStyle::None)
+				.into_iter()
+				.map(|entry| matches!(entry.selection, SearchSelection::File(_)))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(kinds(data()), [false, true]);
+		assert_eq!(kinds(data().with_files_first(true)), [true, false]);
+	}
+
+	fn default_details(query: &str, haystack: &str) -> Option<MatchDetails> {
+		match_details(query, haystack, &MatchOptions::default())
+	}
+
+	#[test]
+	fn test_match_details_reports_score_and_indices() {
+		let details = default_details("bkd", "backend  (11 matches)").unwrap();
+		assert!(details.score > 0);
+		assert_eq!(details.indices, [0, 3, 6]);
+
+		assert_eq!(default_details("", "backend").unwrap().score, 0);
+		assert_eq!(default_details("'xyz", "backend"), None);
+	}
+
+	#[test]
+	fn test_rank_orders_like_the_picker() {
+		let ui = SearchUi::new(
+			SearchData::new()
+				.with_attributes(vec![
+					AttributeRow::new("docs", 4),
+					AttributeRow::new("backend", 11),
+				])
+				.with_files(vec![
+					FileRow::new("docs/api.md", ["docs"]),
+					FileRow::new("src/lib.rs", Vec::<String>::new()),
+				]),
+		)
+		.with_icon_style(IconStyle::None);
+		let names = |ranked: Vec<(SearchSelection, u32)>| -> Vec<String> {
+			ranked
+				.into_iter()
+				.map(|(selection, _)| match selection {
+					SearchSelection::Attribute(attribute) => attribute.name,
+					SearchSelection::File(file) => file.path,
+				})
+				.collect()
+		};
+
+		// An empty query lists everything in display order.
+		assert_eq!(
+			names(ui.rank("")),
+			["backend", "docs", "docs/api.md", "src/lib.rs"]
+		);
+		let ranked = ui.rank("'docs");
+		assert!(ranked.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+		let mut matched = names(ranked);
+		matched.sort();
+		assert_eq!(matched, ["docs", "docs/api.md"]);
+		let ranked = ui.rank("bkd");
+		assert_eq!(ranked.len(), 1);
+		assert!(ranked[0].1 > 0);
+	}
+
+	#[test]
+	fn test_match_options_apply_to_match_details() {
+		// Smart case respects case once the query has an uppercase letter.
+		assert_eq!(default_details("BKD", "backend"), None);
+		let ignore = MatchOptions {
+			case_matching: CaseMatching::Ignore,
+			..MatchOptions::default()
+		};
+		assert!(match_details("BKD", "backend", &ignore).is_some());
+
+		assert_eq!(
+			MatchOptions::default().config(),
+			nucleo_picker::nucleo::Config::DEFAULT
+		);
+		let tuned = MatchOptions {
+			prefer_prefix: true,
+			path_matching: true,
+			..MatchOptions::default()
+		};
+		assert!(tuned.config().prefer_prefix);
+		assert_ne!(tuned.config(), nucleo_picker::nucleo::Config::DEFAULT);
+	}
+
+	#[test]
+	fn test_match_details_indices_count_graphemes() {
+		// Decomposed accent: `e` + U+0301 is one glyph spanning three bytes.
+		let details = default_details("'notes", "cafe\u{301} notes.md").unwrap();
+		assert_eq!(details.indices, [5, 6, 7, 8, 9]);
+
+		// Emoji, including a ZWJ sequence, count as single glyphs.
+		let details = default_details("'party", "🎉 party.md").unwrap();
+		assert_eq!(details.indices, [2, 3, 4, 5, 6]);
+		let details = default_details("'x", "👨\u{200d}👩\u{200d}👧 x").unwrap();
+		assert_eq!(details.indices, [2]);
+
+		// Precomposed `ü` normalizes to `u` and is a single glyph.
+		let details = default_details("'zur", "Zürich/x.txt").unwrap();
+		assert_eq!(details.indices, [0, 1, 2]);
+		let details = default_details("'ich/x", "Zürich/x.txt").unwrap();
+		assert_eq!(details.indices, [3, 4, 5, 6, 7]);
+	}
+
+	#[test]
+	fn test_query_history_round_trip() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("cache/queries.json");
+		assert_eq!(load_query(&path, "/repo/a"), None);
+
+		save_query(&path, "/repo/a", "backend");
+		save_query(&path, "/repo/b", "docs");
+		assert_eq!(load_query(&path, "/repo/a").as_deref(), Some("backend"));
+		assert_eq!(load_query(&path, "/repo/b").as_deref(), Some("docs"));
+
+		fs::write(&path, "not json").unwrap();
+		assert_eq!(load_query(&path, "/repo/a"), None);
+		save_query(&path, "/repo/a", "api");
+		assert_eq!(load_query(&path, "/repo/a").as_deref(), Some("api"));
+	}
+
+	#[test]
+	fn test_shorten_path_substitutes_home() {
+		let home = Some(Path::new("/home/al"));
+		assert_eq!(shorten_path("/home/al", home, 60), "~");
+		assert_eq!(shorten_path("/home/al/src/repo", home, 60), "~/src/repo");
+		// Only whole components match, so a sibling with the same prefix is untouched.
+		assert_eq!(
+			shorten_path("/home/alice/repo", home, 60),
+			"/home/alice/repo"
+		);
+		assert_eq!(shorten_path("/home/al/repo", None, 60), "/home/al/repo");
+		assert_eq!(shorten_path("/home/al/src/repo", home, 8), "~/s…repo");
+	}
+
+	#[test]
+	fn test_truncate_middle_boundaries() {
+		assert_eq!(truncate_middle("abcdefghij", 10), "abcdefghij");
+		assert_eq!(truncate_middle("abcdefghij", 9), "abcd…ghij");
+		assert_eq!(truncate_middle("abcdefghij", 6), "ab…hij");
+		assert_eq!(truncate_middle("abcdefghij", 1), "…");
+		assert_eq!(truncate_middle("abcdefghij", 0), "");
+		// Wide characters are never split and the result never exceeds the width.
+		assert_eq!(truncate_middle("日本語テキスト", 6), "日…ト");
+	}
+
+	#[test]
+	fn test_header_label_combines_title_and_context() {
+		assert_eq!(header_label(None, None, 60), None);
+		assert_eq!(
+			header_label(Some("Select a project tag"), Some("/repo"), 60).as_deref(),
+			Some("Select a project tag — /repo")
+		);
+		assert_eq!(
+			header_label(None, Some("/repo"), 60).as_deref(),
+			Some("/repo")
+		);
+	}
+
+	#[test]
+	fn test_from_paths_builds_untagged_files() {
+		let data = SearchData::from_paths(["src/lib.rs", "README.md"]);
+		assert!(data.attributes.is_empty());
+		assert_eq!(
+			data.files,
+			vec![
+				FileRow::new("src/lib.rs", Vec::<String>::new()),
+				FileRow::new("README.md", Vec::<String>::new()),
+			]
+		);
+	}
+
+	#[test]
+	fn test_quoted_term_is_exact_substring() {
+		let data = || attributes(&["backend", "bookkeeping"]);
+		assert_eq!(matching("bkd", data()).len(), 1);
+		assert!(matching("'bkd", data()).is_empty());
+		assert_eq!(matching("'keep", data()).len(), 1);
+	}
+
+	#[test]
+	fn test_negated_term_excludes() {
+		let found = matching("!legacy", attributes(&["legacy", "frontend"]));
+		assert_eq!(found.len(), 1);
+		assert!(found[0].contains("frontend"));
+	}
+
+	#[test]
+	fn test_anchors_see_the_rendered_decorations() {
+		let data = || {
+			SearchData::new().with_files(vec![
+				FileRow::new("src/routes.rs", ["api"]),
+				FileRow::new("src/routes.rs.bak", Vec::<String>::new()),
+			])
+		};
+		// The icon and tag columns sit at the line edges, not the path.
+		assert!(matching("^src", data()).is_empty());
+		assert!(matching(".rs$", data()).is_empty());
+		assert_eq!(matching("'src/", data()).len(), 2);
+		assert_eq!(matching("[api]$", data()).len(), 1);
+	}
+
+	#[test]
+	fn test_marks_are_drawn_but_not_matched() {
+		let mut entries = build_entries(
+			attributes(&["alpha", "beta"]),
+			&UiConfig,
+			None,
+			IconStyle::None,
+		);
+		add_mark_column(&mut entries);
+		let marks = Marks::new(entries.len());
+		let renderer = EntryRenderer {
+			marks: Some(marks.clone()),
+		};
+		assert_eq!(renderer.render(&entries[0]), "○ alpha  (1 matches)");
+
+		assert!(marks.toggle(0));
+		assert_eq!(renderer.render(&entries[0]), "● alpha  (1 matches)");
+		assert_eq!(renderer.render(&entries[1]), "○ beta   (1 matches)");
+		// Highlights are placed by the matched text, so the drawn line keeps its layout.
+		assert_eq!(
+			renderer.render(&entries[0]).chars().count(),
+			entries[0].render.chars().count()
+		);
+		assert!(!entries[0].render.is_ascii());
+		assert!(!marks.toggle(0));
+		assert_eq!(renderer.render(&entries[0]), entries[0].render);
+	}
+
+	#[test]
+	fn test_screen_writer_stays_on_the_alternate_screen_between_marks() {
+		type Writer<'a> = ScreenWriter<'a, Vec<u8>>;
+		let exit = Cell::new(Exit::Accept);
+		let mut writer = ScreenWriter::new(Vec::new(), &exit);
+		writer.write_all(Writer::ENTER).unwrap();
+		writer.write_all(b"frame").unwrap();
+		exit.set(Exit::Mark);
+		writer.write_all(Writer::LEAVE).unwrap();
+		writer.write_all(Writer::ENTER).unwrap();
+		exit.set(Exit::Accept);
+		writer.write_all(Writer::LEAVE).unwrap();
+		writer.leave_alternate_screen().unwrap();
+		assert_eq!(
+			writer.inner,
+			[Writer::ENTER, b"frame", Writer::LEAVE].concat()
+		);
+
+		// A pick that fails after a mark still gets the screen restored.
+		let exit = Cell::new(Exit::Mark);
+		let mut writer = ScreenWriter::new(Vec::new(), &exit);
+		writer.write_all(Writer::ENTER).unwrap();
+		writer.write_all(Writer::LEAVE).unwrap();
+		writer.leave_alternate_screen().unwrap();
+		assert_eq!(writer.inner, [Writer::ENTER, Writer::LEAVE].concat());
+	}
+
+	#[test]
+	fn test_outcome_selection_is_the_first_selection() {
+		let selections = vec![
+			SearchSelection::Attribute(AttributeRow::new("beta", 1)),
+			SearchSelection::Attribute(AttributeRow::new("alpha", 1)),
+		];
+		let outcome = SearchOutcome::new(PickStatus::Accepted, String::new(), selections.clone());
+		assert_eq!(outcome.selection.as_ref(), selections.first());
+		assert_eq!(outcome.selections, selections);
+		assert!(
+			SearchOutcome::new(PickStatus::Aborted, String::new(), Vec::new())
+				.selection
+				.is_none()
+		);
 	}
 }
@@ -0,0 +1,145 @@
+//! Project-wide defaults from a checked-in `.sparta.toml`.
+//!
+//! The file is found by walking up from a starting directory to the root of the enclosing
+//! repository, so it applies to every command run inside the project. Command-line flags
+//! override it. Unlike the submodule JSON configuration it describes no particular submodule,
+//! only how tags are read and printed.
+//!
+//! ```toml
+//! attribute = "projects"
+//! separators = ",;"
+//! match = "exact"
+//! output = "nul"
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::git::attributes::{MatchMode, PatternOptions, TAG_SEPARATORS};
+
+/// Name of the project configuration file.
+pub const FILE_NAME: &str = ".sparta.toml";
+
+/// How `generate-sparse-list` terminates each printed pattern.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+	/// One pattern per line.
+	#[default]
+	Newline,
+	/// NUL-terminated patterns, as with `--print0`.
+	Nul,
+}
+
+/// Defaults read from `.sparta.toml`; unset keys fall back to the built-in defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+	/// Git attribute holding the tags.
+	pub attribute: Option<String>,
+	/// Characters that separate tags in an attribute value, e.g. `",;"`.
+	pub separators: Option<String>,
+	/// How a tag is compared with each file's tags.
+	#[serde(rename = "match")]
+	pub match_mode: Option<MatchMode>,
+	pub output: Option<OutputFormat>,
+	/// The file this configuration was read from.
+	#[serde(skip)]
+	pub path: Option<PathBuf>,
+}
+
+impl ProjectConfig {
+	/// Parse the configuration file at `path`.
+	pub fn load(path: &Path) -> Result<Self> {
+		let contents = fs::read_to_string(path)
+			.with_context(|| format!("failed to read {}", path.display()))?;
+		let mut config: Self = toml::from_str(&contents)
+			.with_context(|| format!("failed to parse {}", path.display()))?;
+		config.path = Some(path.to_path_buf());
+		Ok(config)
+	}
+
+	/// Load the nearest `.sparta.toml` in `start` or its ancestors, stopping at the directory
+	/// that holds `.git`. No file yields the defaults.
+	pub fn discover(start: &Path) -> Result<Self> {
+		let start = dunce::canonicalize(start)
+			.with_context(|| format!("failed to canonicalize {}", start.display()))?;
+		for dir in start.ancestors() {
+			let candidate = dir.join(FILE_NAME);
+			if candidate.is_file() {
+				return Self::load(&candidate);
+			}
+			if dir.join(".git").exists() {
+				break;
+			}
+		}
+		Ok(Self::default())
+	}
+
+	/// The configured tag separators, or [`TAG_SEPARATORS`].
+	pub fn separators(&self) -> Vec<char> {
+		match &self.separators {
+			Some(separators) => separators.chars().collect(),
+			None => TAG_SEPARATORS.to_vec(),
+		}
+	}
+
+	/// Pattern options with this configuration's match mode and separators.
+	pub fn pattern_options(&self) -> PatternOptions {
+		PatternOptions {
+			matcher: self.match_mode.unwrap_or_default().matcher(),
+			separators: self.separators(),
+			..PatternOptions::default()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_discover_walks_up_to_the_repository_root() {
+		let dir = tempfile::tempdir().unwrap();
+		let repo = dir.path().join("repo");
+		let nested = repo.join("a/b");
+		fs::create_dir_all(&nested).unwrap();
+		fs::create_dir(repo.join(".git")).unwrap();
+
+		// A file above the repository root is not picked up.
+		fs::write(dir.path().join(FILE_NAME), "attribute = \"outside\"\n").unwrap();
+		assert_eq!(
+			ProjectConfig::discover(&nested).unwrap(),
+			ProjectConfig::default()
+		);
+
+		fs::write(
+			repo.join(FILE_NAME),
+			"attribute = \"teams\"\nseparators = \"|\"\nmatch = \"glob\"\noutput = \"nul\"\n",
+		)
+		.unwrap();
+		let config = ProjectConfig::discover(&nested).unwrap();
+		assert_eq!(config.attribute.as_deref(), Some("teams"));
+		assert_eq!(config.separators(), ['|']);
+		assert_eq!(config.match_mode, Some(MatchMode::Glob));
+		assert_eq!(config.output, Some(OutputFormat::Nul));
+		assert_eq!(
+			config.path,
+			Some(dunce::canonicalize(repo.join(FILE_NAME)).unwrap())
+		);
+	}
+
+	#[test]
+	fn test_unknown_keys_are_rejected() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join(FILE_NAME);
+		fs::write(&path, "atribute = \"typo\"\n").unwrap();
+		assert!(ProjectConfig::load(&path).is_err());
+
+		fs::write(&path, "").unwrap();
+		assert_eq!(ProjectConfig::load(&path).unwrap().separators(), [',', ';']);
+	}
+}
@@ -0,0 +1,217 @@
+//! On-disk cache for remote file fetches.
+//!
+//! Each entry is a body file plus a JSON metadata file holding the validators (`ETag`,
+//! `Last-Modified`) needed for a conditional refetch. Anything unreadable or inconsistent is
+//! treated as a miss.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use super::RepoInfo;
+use crate::atomic;
+
+/// Default time a cached file for a branch or tag is used without revalidation.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Identifies one file at one ref of one repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+	pub fn new(repo: &RepoInfo, file_path: &str, git_ref: Option<&str>) -> Self {
+		Self(format!(
+			"{:?}\n{}\n{}\n{}\n{}\n{}",
+			repo.provider,
+			repo.host,
+			repo.owner,
+			repo.repo,
+			git_ref.unwrap_or("HEAD"),
+			file_path.trim_start_matches('/')
+		))
+	}
+
+	/// File stem for this key; FNV-1a so it stays stable across builds.
+	fn file_stem(&self) -> String {
+		let hash = self
+			.0
+			.bytes()
+			.fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+				(hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+			});
+		format!("{:016x}", hash)
+	}
+}
+
+/// Validators and bookkeeping stored next to a cached body.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheMetadata {
+	pub etag: Option<String>,
+	pub last_modified: Option<String>,
+	/// Seconds since the Unix epoch when the body was last fetched or revalidated.
+	pub fetched_at: u64,
+	key: String,
+	size: u64,
+}
+
+/// A cached file body with its metadata.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+	pub body: Vec<u8>,
+	pub metadata: CacheMetadata,
+}
+
+impl CacheEntry {
+	/// Whether the entry was fetched less than `ttl` ago.
+	pub fn is_fresh(&self, ttl: Duration) -> bool {
+		now_secs().saturating_sub(self.metadata.fetched_at) < ttl.as_secs()
+	}
+}
+
+/// A directory of cached remote files.
+#[derive(Debug, Clone)]
+pub struct FetchCache {
+	dir: PathBuf,
+}
+
+impl FetchCache {
+	pub fn new(dir: impl Into<PathBuf>) -> Self {
+		Self { dir: dir.into() }
+	}
+
+	/// The per-user cache directory, if the platform has one.
+	pub fn user_default() -> Option<Self> {
+		ProjectDirs::from("", "", "git-sparta")
+			.map(|dirs| Self::new(dirs.cache_dir().join("remote")))
+	}
+
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	/// Load the entry for `key`; missing, unreadable or inconsistent entries yield `None`.
+	pub fn load(&self, key: &CacheKey) -> Option<CacheEntry> {
+		let (body_path, metadata_path) = self.paths(key);
+		let metadata: CacheMetadata =
+			serde_json::from_str(&fs::read_to_string(metadata_path).ok()?).ok()?;
+		let body = fs::read(body_path).ok()?;
+		(metadata.key == key.0 && metadata.size == body.len() as u64)
+			.then_some(CacheEntry { body, metadata })
+	}
+
+	/// Store `body` for `key` with the response's validators.
+	pub fn store(
+		&self,
+		key: &CacheKey,
+		body: &[u8],
+		etag: Option<String>,
+		last_modified: Option<String>,
+	) -> Result<()> {
+		let metadata = CacheMetadata {
+			etag,
+			last_modified,
+			fetched_at: now_secs(),
+			key: key.0.clone(),
+			size: body.len() as u64,
+		};
+		let (body_path, metadata_path) = self.paths(key);
+		fs::create_dir_all(&self.dir)?;
+		atomic::write(&body_path, body)?;
+		atomic::write(&metadata_path, serde_json::to_string(&metadata)?)
+	}
+
+	/// Mark an entry as just revalidated (e.g. after a `304 Not Modified`).
+	pub fn touch(&self, key: &CacheKey, entry: &CacheEntry) -> Result<()> {
+		let mut metadata = entry.metadata.clone();
+		metadata.fetched_at = now_secs();
+		atomic::write(&self.paths(key).1, serde_json::to_string(&metadata)?)
+	}
+
+	fn paths(&self, key: &CacheKey) -> (PathBuf, PathBuf) {
+		let stem = key.file_stem();
+		(
+			self.dir.join(format!("{}.body", stem)),
+			self.dir.join(format!("{}.json", stem)),
+		)
+	}
+}
+
+/// Whether `git_ref` is a full commit SHA, whose contents can never change.
+pub fn is_immutable_ref(git_ref: Option<&str>) -> bool {
+	git_ref.is_some_and(|git_ref| {
+		matches!(git_ref.len(), 40 | 64) && git_ref.bytes().all(|b| b.is_ascii_hexdigit())
+	})
+}
+
+fn now_secs() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|elapsed| elapsed.as_secs())
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::remote::Provider;
+
+	fn repo() -> RepoInfo {
+		RepoInfo {
+			provider: Provider::GitHub,
+			owner: "owner".to_owned(),
+			repo: "repo".to_owned(),
+			host: "github.com".to_owned(),
+		}
+	}
+
+	#[test]
+	fn test_store_and_load_round_trip() {
+		let dir = tempfile::tempdir().unwrap();
+		let cache = FetchCache::new(dir.path());
+		let key = CacheKey::new(&repo(), ".gitattributes", Some("main"));
+		assert!(cache.load(&key).is_none());
+
+		cache
+			.store(&key, b"* projects=a\n", Some("\"abc\"".to_owned()), None)
+			.unwrap();
+		let entry = cache.load(&key).unwrap();
+		assert_eq!(entry.body, b"* projects=a\n");
+		assert_eq!(entry.metadata.etag.as_deref(), Some("\"abc\""));
+		assert!(entry.is_fresh(DEFAULT_TTL));
+		assert!(!entry.is_fresh(Duration::ZERO));
+
+		// Another ref is a different entry.
+		let other = CacheKey::new(&repo(), ".gitattributes", Some("dev"));
+		assert!(cache.load(&other).is_none());
+	}
+
+	#[test]
+	fn test_corrupt_entries_are_misses() {
+		let dir = tempfile::tempdir().unwrap();
+		let cache = FetchCache::new(dir.path());
+		let key = CacheKey::new(&repo(), "README.md", None);
+		cache.store(&key, b"hello", None, None).unwrap();
+		let (body_path, metadata_path) = cache.paths(&key);
+
+		fs::write(&body_path, b"truncated").unwrap();
+		assert!(cache.load(&key).is_none());
+
+		cache.store(&key, b"hello", None, None).unwrap();
+		fs::write(&metadata_path, "{not json").unwrap();
+		assert!(cache.load(&key).is_none());
+	}
+
+	#[test]
+	fn test_is_immutable_ref() {
+		assert!(is_immutable_ref(Some(
+			"0123456789abcdef0123456789abcdef01234567"
+		)));
+		assert!(!is_immutable_ref(Some("main")));
+		assert!(!is_immutable_ref(Some("0123456")));
+		assert!(!is_immutable_ref(None));
+	}
+}
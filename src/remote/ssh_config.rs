@@ -0,0 +1,108 @@
+//! Resolve SSH host aliases through an OpenSSH client configuration.
+//!
+//! Only `Host` blocks and their `HostName` directive are understood; `Match` blocks and
+//! `Include` are skipped, which is enough to map `git@work-gh:owner/repo` to `github.com`.
+
+use std::path::PathBuf;
+
+use directories::BaseDirs;
+use globset::Glob;
+
+/// The current user's `~/.ssh/config`.
+pub fn user_config_path() -> Option<PathBuf> {
+	BaseDirs::new().map(|dirs| dirs.home_dir().join(".ssh").join("config"))
+}
+
+/// The `HostName` that `config` assigns to `alias`, if any.
+///
+/// As with ssh, the first matching value wins and `%h` expands to the alias itself.
+pub fn host_name(config: &str, alias: &str) -> Option<String> {
+	let mut in_matching_block = true;
+	for line in config.lines() {
+		let Some((keyword, value)) = split_directive(line) else {
+			continue;
+		};
+		if keyword.eq_ignore_ascii_case("host") {
+			in_matching_block = host_patterns_match(value, alias);
+		} else if keyword.eq_ignore_ascii_case("match") {
+			in_matching_block = false;
+		} else if in_matching_block && keyword.eq_ignore_ascii_case("hostname") {
+			return Some(value.replace("%h", alias));
+		}
+	}
+	None
+}
+
+/// Split `Keyword value` or `Keyword=value`, skipping blanks and comments.
+fn split_directive(line: &str) -> Option<(&str, &str)> {
+	let line = line.trim();
+	if line.is_empty() || line.starts_with('#') {
+		return None;
+	}
+	let split = line.find(|c: char| c.is_whitespace() || c == '=')?;
+	let (keyword, rest) = line.split_at(split);
+	let value = rest
+		.trim_start()
+		.strip_prefix('=')
+		.unwrap_or(rest)
+		.trim()
+		.trim_matches('"');
+	Some((keyword, value))
+}
+
+/// Whether `alias` matches a `Host` line: any pattern matches and no `!pattern` does.
+fn host_patterns_match(patterns: &str, alias: &str) -> bool {
+	let alias = alias.to_ascii_lowercase();
+	let matches = |pattern: &str| {
+		Glob::new(&pattern.to_ascii_lowercase())
+			.map(|glob| glob.compile_matcher().is_match(&alias))
+			.unwrap_or(false)
+	};
+
+	let mut matched = false;
+	for pattern in patterns.split_whitespace() {
+		match pattern.strip_prefix('!') {
+			Some(negated) if matches(negated) => return false,
+			Some(_) => {}
+			None => matched |= matches(pattern),
+		}
+	}
+	matched
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CONFIG: &str = "\
+# personal
+Host work-gh gh-*
+    HostName github.com
+    User git
+
+Host !corp-lab *lab
+  HostName=gitlab.%h.example.com
+
+Match host other
+    HostName wrong.example.com
+
+Host *
+    IdentityFile ~/.ssh/id_ed25519
+";
+
+	#[test]
+	fn test_host_name_resolves_aliases() {
+		assert_eq!(host_name(CONFIG, "work-gh").as_deref(), Some("github.com"));
+		assert_eq!(
+			host_name(CONFIG, "GH-Second").as_deref(),
+			Some("github.com")
+		);
+		assert_eq!(
+			host_name(CONFIG, "mylab").as_deref(),
+			Some("gitlab.mylab.example.com")
+		);
+		assert_eq!(host_name(CONFIG, "corp-lab"), None);
+		assert_eq!(host_name(CONFIG, "other"), None);
+		assert_eq!(host_name(CONFIG, "github.com"), None);
+	}
+}